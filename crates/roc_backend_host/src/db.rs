@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+pub use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use tracing::{debug, instrument};
+
+static DATABASE: OnceLock<Mutex<Connection>> = OnceLock::new();
+
+/// Opens the app's database, creating the file and its directory if needed
+pub fn open(path: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let connection = Connection::open(path)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+
+    DATABASE
+        .set(Mutex::new(connection))
+        .map_err(|_| anyhow::anyhow!("Database is already open"))
+}
+
+/// Statements the roc backend asked to run through `Db.query` or `Db.transaction`
+#[derive(Debug)]
+pub struct Request {
+    pub id: u64,
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug)]
+pub struct Statement {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+#[derive(Debug)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    pub rows_affected: u64,
+    pub last_insert_id: i64,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NoDatabase,
+    Sql(String),
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Sql(err.to_string())
+    }
+}
+
+/// Runs the statements in a single transaction on a blocking thread
+#[instrument(skip_all, fields(id = request.id))]
+pub async fn run(request: Request) -> Result<Vec<QueryResult>, Error> {
+    tokio::task::spawn_blocking(move || run_blocking(&request.statements))
+        .await
+        .map_err(|err| Error::Sql(err.to_string()))?
+}
+
+fn run_blocking(statements: &[Statement]) -> Result<Vec<QueryResult>, Error> {
+    let mut connection = DATABASE
+        .get()
+        .ok_or(Error::NoDatabase)?
+        .lock()
+        .expect("Could not acquire database lock");
+
+    run_in(&mut connection, statements)
+}
+
+fn run_in(
+    connection: &mut Connection,
+    statements: &[Statement],
+) -> Result<Vec<QueryResult>, Error> {
+    // Dropping the transaction without committing rolls it back
+    let transaction = connection.transaction()?;
+    let mut results = Vec::with_capacity(statements.len());
+    for statement in statements {
+        let mut prepared = transaction.prepare(&statement.sql)?;
+        let columns = prepared
+            .column_names()
+            .into_iter()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+
+        let mut rows = Vec::new();
+        let mut query = prepared.query(params_from_iter(statement.params.iter()))?;
+        while let Some(row) = query.next()? {
+            rows.push(
+                (0..columns.len())
+                    .map(|i| row.get::<_, Value>(i))
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+        drop(query);
+
+        let rows_affected = if prepared.readonly() {
+            0
+        } else {
+            transaction.changes()
+        };
+        results.push(QueryResult {
+            columns,
+            rows,
+            rows_affected,
+            last_insert_id: transaction.last_insert_rowid(),
+        });
+    }
+    transaction.commit()?;
+    debug!(statements = statements.len(), "Committed transaction");
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statement(sql: &str, params: Vec<Value>) -> Statement {
+        Statement {
+            sql: sql.to_owned(),
+            params,
+        }
+    }
+
+    fn open_in_memory() -> Connection {
+        let mut connection = Connection::open_in_memory().unwrap();
+        run_in(
+            &mut connection,
+            &[statement(
+                "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT UNIQUE, weight REAL, data BLOB, note TEXT)",
+                Vec::new(),
+            )],
+        )
+        .unwrap();
+        connection
+    }
+
+    fn count(connection: &mut Connection) -> Vec<Vec<Value>> {
+        run_in(
+            connection,
+            &[statement("SELECT COUNT(*) FROM items", Vec::new())],
+        )
+        .unwrap()
+        .remove(0)
+        .rows
+    }
+
+    #[test]
+    fn every_kind_of_value_is_bound_and_read_back() {
+        let mut connection = open_in_memory();
+        let values = vec![
+            Value::Integer(7),
+            Value::Text("anvil".to_owned()),
+            Value::Real(2.5),
+            Value::Blob(vec![0, 1, 255]),
+            Value::Null,
+        ];
+
+        let results = run_in(
+            &mut connection,
+            &[
+                statement("INSERT INTO items VALUES (?, ?, ?, ?, ?)", values.clone()),
+                statement(
+                    "SELECT id, name, weight, data, note FROM items WHERE id = ?",
+                    vec![Value::Integer(7)],
+                ),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(results[0].rows_affected, 1);
+        assert_eq!(results[0].last_insert_id, 7);
+        assert_eq!(results[1].columns, ["id", "name", "weight", "data", "note"]);
+        assert_eq!(results[1].rows, [values]);
+        assert_eq!(results[1].rows_affected, 0);
+    }
+
+    #[test]
+    fn statements_of_a_transaction_are_committed_together() {
+        let mut connection = open_in_memory();
+
+        let results = run_in(
+            &mut connection,
+            &[
+                statement(
+                    "INSERT INTO items (name) VALUES (?)",
+                    vec![Value::Text("anvil".to_owned())],
+                ),
+                statement(
+                    "INSERT INTO items (name) VALUES (?)",
+                    vec![Value::Text("hammer".to_owned())],
+                ),
+                statement("UPDATE items SET weight = ?", vec![Value::Real(1.0)]),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1].last_insert_id, 2);
+        assert_eq!(results[2].rows_affected, 2);
+        assert_eq!(count(&mut connection), [[Value::Integer(2)]]);
+    }
+
+    #[test]
+    fn a_failing_statement_rolls_back_the_ones_before_it() {
+        let mut connection = open_in_memory();
+
+        let result = run_in(
+            &mut connection,
+            &[
+                statement(
+                    "INSERT INTO items (name) VALUES (?)",
+                    vec![Value::Text("anvil".to_owned())],
+                ),
+                statement(
+                    "INSERT INTO items (name) VALUES (?)",
+                    vec![Value::Text("anvil".to_owned())],
+                ),
+            ],
+        );
+
+        assert!(matches!(result, Err(Error::Sql(message)) if message.contains("UNIQUE")));
+        assert_eq!(count(&mut connection), [[Value::Integer(0)]]);
+    }
+
+    #[test]
+    fn queries_fail_until_a_database_is_opened() {
+        // No test opens the shared database
+        let result = run_blocking(&[statement("SELECT 1", Vec::new())]);
+
+        assert!(matches!(result, Err(Error::NoDatabase)));
+    }
+}
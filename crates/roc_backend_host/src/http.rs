@@ -0,0 +1,298 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use reqwest::{Client, Method};
+use tracing::{debug, instrument};
+
+use crate::roc;
+
+/// Longest any request may take, including those made with `NoTimeout`. A timeout given
+/// with the request replaces it
+const MAX_REQUEST_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// Largest response body read into memory, larger ones fail with a network error
+const MAX_RESPONSE_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+static CLIENT: LazyLock<Client> = LazyLock::new(|| client(MAX_REQUEST_DURATION));
+
+fn client(timeout: Duration) -> Client {
+    Client::builder()
+        .timeout(timeout)
+        .build()
+        .expect("Could not build the HTTP client")
+}
+
+/// An outbound request made by the roc backend through `Http.request`
+#[derive(Debug)]
+pub struct Request {
+    pub id: u64,
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    timeout: Option<Duration>,
+}
+
+impl From<&roc::HttpRequest> for Request {
+    fn from(request: &roc::HttpRequest) -> Self {
+        Request {
+            id: request.id,
+            method: request.method.as_str().to_owned(),
+            url: request.url.as_str().to_owned(),
+            headers: request
+                .headers
+                .iter()
+                .map(|header| {
+                    (
+                        header.name.as_str().to_owned(),
+                        header.value.as_str().to_owned(),
+                    )
+                })
+                .collect(),
+            body: request.body.as_slice().to_vec(),
+            timeout: (request.timeout_ms > 0).then(|| Duration::from_millis(request.timeout_ms)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Timeout,
+    Network(String),
+    BadRequest(String),
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Error::Timeout
+        } else if err.is_builder() {
+            Error::BadRequest(err.to_string())
+        } else {
+            Error::Network(err.to_string())
+        }
+    }
+}
+
+#[instrument(skip_all, fields(id = request.id, method = %request.method, url = %request.url))]
+pub async fn send(request: Request) -> Result<Response, Error> {
+    send_with(&CLIENT, request, MAX_RESPONSE_BODY_BYTES).await
+}
+
+async fn send_with(
+    client: &Client,
+    request: Request,
+    max_body_bytes: usize,
+) -> Result<Response, Error> {
+    let method = Method::from_bytes(request.method.as_bytes())
+        .map_err(|err| Error::BadRequest(err.to_string()))?;
+
+    let mut builder = client.request(method, &request.url).body(request.body);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(timeout) = request.timeout {
+        builder = builder.timeout(timeout);
+    }
+
+    let mut response = builder.send().await?;
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.as_str().to_owned(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+    let too_large = || {
+        Error::Network(format!(
+            "Response body is larger than {max_body_bytes} bytes"
+        ))
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_body_bytes as u64)
+    {
+        return Err(too_large());
+    }
+    // The length is only a hint, the body is counted as it arrives
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_body_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    debug!(status, "Received response");
+
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::{get, post};
+    use axum::Router;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Serves the router on a free local port, returning its address
+    async fn serve(router: Router) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        format!("http://{address}")
+    }
+
+    fn request(method: &str, url: String) -> Request {
+        Request {
+            id: 1,
+            method: method.to_owned(),
+            url,
+            headers: Vec::new(),
+            body: Vec::new(),
+            timeout: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_the_status_headers_and_body() {
+        let echo = |headers: HeaderMap, body: String| async move {
+            let greeting = headers["x-greeting"].to_str().unwrap().to_owned();
+            (
+                StatusCode::CREATED,
+                [("x-echo", greeting)],
+                format!("got {body}"),
+            )
+        };
+        let url = serve(Router::new().route("/echo", post(echo))).await;
+
+        let response = send(Request {
+            headers: vec![("x-greeting".to_owned(), "hello".to_owned())],
+            body: b"ping".to_vec(),
+            ..request("POST", format!("{url}/echo"))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(response.status, 201);
+        assert!(response
+            .headers
+            .contains(&("x-echo".to_owned(), "hello".to_owned())));
+        assert_eq!(response.body, b"got ping");
+    }
+
+    #[tokio::test]
+    async fn error_statuses_are_responses() {
+        let url = serve(Router::new()).await;
+
+        let response = send(request("GET", format!("{url}/missing")))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status, 404);
+    }
+
+    #[tokio::test]
+    async fn slow_responses_time_out() {
+        let slow = || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "too late"
+        };
+        let url = serve(Router::new().route("/slow", get(slow))).await;
+
+        let result = send(Request {
+            timeout: Some(Duration::from_millis(50)),
+            ..request("GET", format!("{url}/slow"))
+        })
+        .await;
+
+        assert!(matches!(result, Err(Error::Timeout)), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn requests_without_a_timeout_are_still_cut_off() {
+        let slow = || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "too late"
+        };
+        let url = serve(Router::new().route("/slow", get(slow))).await;
+
+        let result = send_with(
+            &client(Duration::from_millis(50)),
+            request("GET", format!("{url}/slow")),
+            MAX_RESPONSE_BODY_BYTES,
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::Timeout)), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn bodies_up_to_the_limit_are_read() {
+        let url = serve(Router::new().route("/body", get(|| async { "0123456789" }))).await;
+
+        let response = send_with(&CLIENT, request("GET", format!("{url}/body")), 10)
+            .await
+            .unwrap();
+
+        assert_eq!(response.body, b"0123456789");
+    }
+
+    #[tokio::test]
+    async fn larger_bodies_are_refused() {
+        let url = serve(Router::new().route("/body", get(|| async { "0123456789" }))).await;
+
+        let result = send_with(&CLIENT, request("GET", format!("{url}/body")), 9).await;
+
+        assert!(matches!(result, Err(Error::Network(_))), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn larger_bodies_of_unknown_length_are_refused() {
+        let stream = || async {
+            let chunks = ["01234", "56789"].map(Ok::<_, std::io::Error>);
+            axum::body::Body::from_stream(futures::stream::iter(chunks))
+        };
+        let url = serve(Router::new().route("/stream", get(stream))).await;
+
+        let result = send_with(&CLIENT, request("GET", format!("{url}/stream")), 9).await;
+
+        assert!(matches!(result, Err(Error::Network(_))), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn refused_connections_are_network_errors() {
+        // A port that was free a moment ago, with nothing listening on it anymore
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let result = send(request("GET", format!("http://{address}/"))).await;
+
+        assert!(matches!(result, Err(Error::Network(_))), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn invalid_methods_are_bad_requests() {
+        let result = send(request("NOT A METHOD", "http://127.0.0.1/".to_owned())).await;
+
+        assert!(matches!(result, Err(Error::BadRequest(_))), "{result:?}");
+    }
+}
@@ -0,0 +1,360 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use tokio::sync::Notify;
+use tracing::{debug, instrument};
+
+/// Longest time a failed job waits before its next attempt
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// How often idle workers look for due jobs when nothing wakes them up earlier
+const IDLE_POLL: Duration = Duration::from_secs(60);
+
+static QUEUE: OnceLock<Queue> = OnceLock::new();
+
+struct Queue {
+    connection: Mutex<Connection>,
+    new_jobs: Notify,
+}
+
+/// A job the roc backend queued through `Job.enqueue`
+#[derive(Debug)]
+pub struct NewJob {
+    pub name: String,
+    pub payload: Vec<u8>,
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+}
+
+/// A queued job claimed by a worker
+#[derive(Debug)]
+pub struct Job {
+    pub id: i64,
+    pub name: String,
+    pub payload: Vec<u8>,
+    /// Attempts including the one about to be made
+    pub attempts: u32,
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+}
+
+impl Job {
+    /// Delay before the next attempt, doubling with every failed attempt
+    pub fn backoff(&self) -> Duration {
+        let factor = 2u64.saturating_pow(self.attempts.saturating_sub(1));
+        Duration::from_millis(self.initial_backoff_ms.saturating_mul(factor)).min(MAX_BACKOFF)
+    }
+}
+
+/// Opens the persisted queue, putting back jobs that were running when the host stopped
+pub fn open(path: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let connection = Connection::open(path)?;
+    prepare(&connection)?;
+
+    QUEUE
+        .set(Queue {
+            connection: Mutex::new(connection),
+            new_jobs: Notify::new(),
+        })
+        .map_err(|_| anyhow::anyhow!("Job queue is already open"))
+}
+
+fn prepare(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch(
+        "PRAGMA journal_mode = WAL;
+        CREATE TABLE IF NOT EXISTS jobs (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            payload BLOB NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            max_attempts INTEGER NOT NULL,
+            initial_backoff_ms INTEGER NOT NULL,
+            run_at INTEGER NOT NULL,
+            running INTEGER NOT NULL DEFAULT 0
+        );
+        UPDATE jobs SET running = 0;",
+    )
+}
+
+#[instrument(skip_all, fields(name = job.name))]
+pub fn enqueue(job: NewJob) -> anyhow::Result<()> {
+    let queue = queue()?;
+    insert(
+        &queue
+            .connection
+            .lock()
+            .expect("Could not acquire job queue lock"),
+        &job,
+    )?;
+    queue.new_jobs.notify_one();
+    debug!("Queued job");
+
+    Ok(())
+}
+
+/// Waits until a job is due and claims it for the calling worker
+pub async fn next() -> anyhow::Result<Job> {
+    let queue = queue()?;
+    loop {
+        let wait = {
+            let connection = queue
+                .connection
+                .lock()
+                .expect("Could not acquire job queue lock");
+            if let Some(job) = claim_due(&connection)? {
+                return Ok(job);
+            }
+
+            let next_run_at: Option<i64> = connection.query_row(
+                "SELECT MIN(run_at) FROM jobs WHERE running = 0",
+                [],
+                |row| row.get(0),
+            )?;
+            next_run_at
+                .map(|run_at| Duration::from_millis((run_at - now_ms()).max(0) as u64))
+                .unwrap_or(IDLE_POLL)
+                .min(IDLE_POLL)
+        };
+
+        tokio::select! {
+            _ = queue.new_jobs.notified() => {}
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
+
+fn insert(connection: &Connection, job: &NewJob) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT INTO jobs (name, payload, max_attempts, initial_backoff_ms, run_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            job.name,
+            job.payload,
+            job.max_attempts.max(1),
+            job.initial_backoff_ms,
+            now_ms()
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn claim_due(connection: &Connection) -> rusqlite::Result<Option<Job>> {
+    let job = connection
+        .query_row(
+            "SELECT id, name, payload, attempts, max_attempts, initial_backoff_ms FROM jobs
+            WHERE running = 0 AND run_at <= ?1
+            ORDER BY run_at, id
+            LIMIT 1",
+            params![now_ms()],
+            |row| {
+                Ok(Job {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    payload: row.get(2)?,
+                    attempts: row.get::<_, u32>(3)? + 1,
+                    max_attempts: row.get(4)?,
+                    initial_backoff_ms: row.get(5)?,
+                })
+            },
+        )
+        .optional()?;
+
+    if let Some(job) = &job {
+        connection.execute(
+            "UPDATE jobs SET running = 1, attempts = ?2 WHERE id = ?1",
+            params![job.id, job.attempts],
+        )?;
+    }
+
+    Ok(job)
+}
+
+/// Removes a job that succeeded or will not be attempted again
+pub fn finish(job: &Job) -> anyhow::Result<()> {
+    queue()?
+        .connection
+        .lock()
+        .expect("Could not acquire job queue lock")
+        .execute("DELETE FROM jobs WHERE id = ?1", params![job.id])?;
+
+    Ok(())
+}
+
+/// Puts a failed job back in the queue to run again after its backoff
+pub fn retry(job: &Job) -> anyhow::Result<()> {
+    let run_at = now_ms() + job.backoff().as_millis() as i64;
+    let queue = queue()?;
+    reschedule(
+        &queue
+            .connection
+            .lock()
+            .expect("Could not acquire job queue lock"),
+        job,
+        run_at,
+    )?;
+    queue.new_jobs.notify_one();
+
+    Ok(())
+}
+
+fn reschedule(connection: &Connection, job: &Job, run_at: i64) -> rusqlite::Result<()> {
+    connection.execute(
+        "UPDATE jobs SET running = 0, run_at = ?2 WHERE id = ?1",
+        params![job.id, run_at],
+    )?;
+
+    Ok(())
+}
+
+fn queue() -> anyhow::Result<&'static Queue> {
+    QUEUE
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("The app has no jobs registered"))
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn job(attempts: u32, initial_backoff_ms: u64) -> Job {
+        Job {
+            id: 1,
+            name: "resize".to_owned(),
+            payload: Vec::new(),
+            attempts,
+            max_attempts: 3,
+            initial_backoff_ms,
+        }
+    }
+
+    fn new_job(max_attempts: u32) -> NewJob {
+        NewJob {
+            name: "resize".to_owned(),
+            payload: b"image.png".to_vec(),
+            max_attempts,
+            initial_backoff_ms: 1000,
+        }
+    }
+
+    fn open_in_memory() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        prepare(&connection).unwrap();
+        connection
+    }
+
+    /// A database file of its own for the test, removed when it is dropped
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("galena-jobs-{}-{name}.sqlite", std::process::id()));
+            _ = fs::remove_file(&path);
+            TempDb(path)
+        }
+
+        fn open(&self) -> Connection {
+            let connection = Connection::open(&self.0).unwrap();
+            prepare(&connection).unwrap();
+            connection
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = self.0.clone().into_os_string();
+                path.push(suffix);
+                _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_with_every_attempt() {
+        assert_eq!(job(1, 1000).backoff(), Duration::from_secs(1));
+        assert_eq!(job(2, 1000).backoff(), Duration::from_secs(2));
+        assert_eq!(job(3, 1000).backoff(), Duration::from_secs(4));
+        assert_eq!(job(4, 250).backoff(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(job(13, 1000).backoff(), MAX_BACKOFF);
+        assert_eq!(job(100, 1000).backoff(), MAX_BACKOFF);
+        assert_eq!(job(u32::MAX, u64::MAX).backoff(), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn a_job_without_a_backoff_is_retried_right_away() {
+        assert_eq!(job(5, 0).backoff(), Duration::ZERO);
+    }
+
+    #[test]
+    fn jobs_are_claimed_once() {
+        let connection = open_in_memory();
+        insert(&connection, &new_job(3)).unwrap();
+
+        let claimed = claim_due(&connection).unwrap().unwrap();
+        assert_eq!(claimed.name, "resize");
+        assert_eq!(claimed.payload, b"image.png");
+        assert_eq!(claimed.attempts, 1);
+        assert!(claim_due(&connection).unwrap().is_none());
+    }
+
+    #[test]
+    fn jobs_are_attempted_at_least_once() {
+        let connection = open_in_memory();
+        insert(&connection, &new_job(0)).unwrap();
+
+        assert_eq!(claim_due(&connection).unwrap().unwrap().max_attempts, 1);
+    }
+
+    #[test]
+    fn retried_jobs_wait_for_their_backoff() {
+        let connection = open_in_memory();
+        insert(&connection, &new_job(3)).unwrap();
+        let claimed = claim_due(&connection).unwrap().unwrap();
+
+        reschedule(&connection, &claimed, now_ms() + 60_000).unwrap();
+        assert!(claim_due(&connection).unwrap().is_none());
+
+        reschedule(&connection, &claimed, now_ms()).unwrap();
+        let retried = claim_due(&connection).unwrap().unwrap();
+        assert_eq!(retried.id, claimed.id);
+        assert_eq!(retried.attempts, 2);
+    }
+
+    #[test]
+    fn queued_jobs_survive_a_restart() {
+        let db = TempDb::new("restart");
+        let claimed = {
+            let connection = db.open();
+            insert(&connection, &new_job(3)).unwrap();
+            claim_due(&connection).unwrap().unwrap()
+        };
+
+        // The job was running when the host stopped, so it is put back
+        let connection = db.open();
+        let reclaimed = claim_due(&connection).unwrap().unwrap();
+        assert_eq!(reclaimed.id, claimed.id);
+        assert_eq!(reclaimed.payload, b"image.png");
+        assert_eq!(reclaimed.attempts, 2);
+    }
+}
@@ -2,7 +2,11 @@ use std::sync::OnceLock;
 
 use tokio::{runtime::Runtime, sync::mpsc::Sender};
 
+mod db;
+mod http;
+mod jobs;
 mod roc;
+mod secrets;
 mod server;
 
 #[derive(Debug, Clone)]
@@ -11,8 +15,43 @@ pub struct MessageInfo {
     pub msg_bytes: String,
 }
 
+impl MessageInfo {
+    /// Wraps an encoded `ToFrontendMsg` in the frame the frontend expects, `reply_to` being
+    /// the id of the request it answers or 0 when it is not a reply
+    pub fn new(client_id: String, reply_to: u64, message: &str) -> Self {
+        MessageInfo {
+            client_id,
+            msg_bytes: format!(r#"{{"reply_to":{reply_to},"msg":{message}}}"#),
+        }
+    }
+}
+
+/// What the task that owns the websockets is asked to do
+#[derive(Debug, Clone)]
+pub enum Outgoing {
+    Message(MessageInfo),
+    /// Closes the websocket of a client, or every websocket of a session
+    Close {
+        target: CloseTarget,
+        code: u16,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum CloseTarget {
+    Client(String),
+    Session(String),
+}
+
+impl From<MessageInfo> for Outgoing {
+    fn from(message: MessageInfo) -> Self {
+        Outgoing::Message(message)
+    }
+}
+
 pub static ASYNC_RUNTIME: OnceLock<Runtime> = OnceLock::new();
-pub static CHANNEL_SENDER: OnceLock<Sender<MessageInfo>> = OnceLock::new();
+pub static CHANNEL_SENDER: OnceLock<Sender<Outgoing>> = OnceLock::new();
 
 #[no_mangle]
 pub extern "C" fn rust_main() -> isize {
@@ -1,9 +1,9 @@
 use core::ffi::c_void;
 use std::mem::ManuallyDrop;
 
-use roc_std::{RocBox, RocResult, RocStr};
+use roc_std::{RocBox, RocList, RocResult, RocStr};
 
-use crate::{MessageInfo, ASYNC_RUNTIME, CHANNEL_SENDER};
+use crate::{db, http, jobs, secrets, MessageInfo, ASYNC_RUNTIME, CHANNEL_SENDER};
 
 #[derive(Clone, Debug)]
 pub struct Model {
@@ -29,44 +29,626 @@ impl Model {
 unsafe impl Send for Model {}
 unsafe impl Sync for Model {}
 
-pub fn call_roc_backend_init() -> RocBox<()> {
-    extern "C" {
-        #[link_name = "roc__backend_init_for_host_1_exposed"]
-        pub fn caller() -> RocBox<()>;
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct ToFrontend {
+    pub client_id: roc_std::RocStr,
+    pub message: roc_std::RocStr,
+    /// The id of the request this message answers, 0 when it is not a reply
+    pub reply_to: u64,
+}
 
-        // #[link_name = "roc__backend_init_for_host_1_exposed_size"]
-        // fn size() -> i64;
+impl roc_std::RocRefcounted for ToFrontend {
+    fn inc(&mut self) {
+        self.client_id.inc();
+        self.message.inc();
+    }
+    fn dec(&mut self) {
+        self.client_id.dec();
+        self.message.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
     }
+}
+
+/// A websocket, or all websockets of a session, the roc backend wants closed
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct Disconnect {
+    /// A client id, or a session id when `session` is set
+    pub id: roc_std::RocStr,
+    pub reason: roc_std::RocStr,
+    pub code: u16,
+    pub session: bool,
+}
 
-    unsafe { caller() }
+impl roc_std::RocRefcounted for Disconnect {
+    fn inc(&mut self) {
+        self.id.inc();
+        self.reason.inc();
+    }
+    fn dec(&mut self) {
+        self.id.dec();
+        self.reason.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
 }
 
+/// A client joining or leaving a topic
 #[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
 #[repr(C)]
-pub struct ToFrontend {
+pub struct TopicChange {
     pub client_id: roc_std::RocStr,
+    pub topic: roc_std::RocStr,
+    /// False when the client leaves the topic
+    pub subscribe: bool,
+}
+
+impl roc_std::RocRefcounted for TopicChange {
+    fn inc(&mut self) {
+        self.client_id.inc();
+        self.topic.inc();
+    }
+    fn dec(&mut self) {
+        self.client_id.dec();
+        self.topic.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+/// An encoded `ToFrontendMsg` for every client subscribed to `topic`
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct Published {
     pub message: roc_std::RocStr,
+    pub topic: roc_std::RocStr,
+}
+
+impl roc_std::RocRefcounted for Published {
+    fn inc(&mut self) {
+        self.message.inc();
+        self.topic.inc();
+    }
+    fn dec(&mut self) {
+        self.message.dec();
+        self.topic.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct HttpHeader {
+    pub name: roc_std::RocStr,
+    pub value: roc_std::RocStr,
+}
+
+impl roc_std::RocRefcounted for HttpHeader {
+    fn inc(&mut self) {
+        self.name.inc();
+        self.value.inc();
+    }
+    fn dec(&mut self) {
+        self.name.dec();
+        self.value.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+/// A key/value pair given to `Log.info!` and friends
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct LogField {
+    pub f0: roc_std::RocStr,
+    pub f1: roc_std::RocStr,
+}
+
+impl roc_std::RocRefcounted for LogField {
+    fn inc(&mut self) {
+        self.f0.inc();
+        self.f1.inc();
+    }
+    fn dec(&mut self) {
+        self.f0.dec();
+        self.f1.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct HttpRequest {
+    pub body: RocList<u8>,
+    pub headers: RocList<HttpHeader>,
+    pub id: u64,
+    pub method: roc_std::RocStr,
+    /// 0 means the request never times out
+    pub timeout_ms: u64,
+    pub url: roc_std::RocStr,
+}
+
+impl roc_std::RocRefcounted for HttpRequest {
+    fn inc(&mut self) {
+        self.body.inc();
+        self.headers.inc();
+        self.method.inc();
+        self.url.inc();
+    }
+    fn dec(&mut self) {
+        self.body.dec();
+        self.headers.dec();
+        self.method.dec();
+        self.url.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct HttpResponse {
+    pub body: RocList<u8>,
+    pub error: roc_std::RocStr,
+    pub headers: RocList<HttpHeader>,
+    pub status: u16,
+    /// 0 when a response was received, 1 on timeout, 2 on a network error and 3 when the
+    /// request could not be built
+    pub failure: u8,
+}
+
+impl From<Result<http::Response, http::Error>> for HttpResponse {
+    fn from(outcome: Result<http::Response, http::Error>) -> Self {
+        match outcome {
+            Ok(response) => HttpResponse {
+                body: RocList::from_slice(&response.body),
+                headers: response
+                    .headers
+                    .iter()
+                    .map(|(name, value)| HttpHeader {
+                        name: RocStr::from(name.as_str()),
+                        value: RocStr::from(value.as_str()),
+                    })
+                    .collect(),
+                status: response.status,
+                ..Default::default()
+            },
+            Err(http::Error::Timeout) => HttpResponse {
+                failure: 1,
+                ..Default::default()
+            },
+            Err(http::Error::Network(error)) => HttpResponse {
+                failure: 2,
+                error: RocStr::from(error.as_str()),
+                ..Default::default()
+            },
+            Err(http::Error::BadRequest(error)) => HttpResponse {
+                failure: 3,
+                error: RocStr::from(error.as_str()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// 0 is null, 1 an integer, 2 a real, 3 utf8 text and 4 a blob
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct DbValue {
+    pub bytes: RocList<u8>,
+    pub integer: i64,
+    pub real: f64,
+    pub kind: u8,
+}
+
+impl roc_std::RocRefcounted for DbValue {
+    fn inc(&mut self) {
+        self.bytes.inc();
+    }
+    fn dec(&mut self) {
+        self.bytes.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+impl From<&DbValue> for db::Value {
+    fn from(value: &DbValue) -> Self {
+        match value.kind {
+            1 => db::Value::Integer(value.integer),
+            2 => db::Value::Real(value.real),
+            3 => db::Value::Text(String::from_utf8_lossy(value.bytes.as_slice()).into_owned()),
+            4 => db::Value::Blob(value.bytes.as_slice().to_vec()),
+            _ => db::Value::Null,
+        }
+    }
+}
+
+impl From<&db::Value> for DbValue {
+    fn from(value: &db::Value) -> Self {
+        match value {
+            db::Value::Null => DbValue::default(),
+            db::Value::Integer(integer) => DbValue {
+                kind: 1,
+                integer: *integer,
+                ..Default::default()
+            },
+            db::Value::Real(real) => DbValue {
+                kind: 2,
+                real: *real,
+                ..Default::default()
+            },
+            db::Value::Text(text) => DbValue {
+                kind: 3,
+                bytes: RocList::from_slice(text.as_bytes()),
+                ..Default::default()
+            },
+            db::Value::Blob(blob) => DbValue {
+                kind: 4,
+                bytes: RocList::from_slice(blob),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct DbStatement {
+    pub params: RocList<DbValue>,
+    pub sql: roc_std::RocStr,
+}
+
+impl roc_std::RocRefcounted for DbStatement {
+    fn inc(&mut self) {
+        self.params.inc();
+        self.sql.inc();
+    }
+    fn dec(&mut self) {
+        self.params.dec();
+        self.sql.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct DbRequest {
+    pub id: u64,
+    pub statements: RocList<DbStatement>,
+}
+
+impl roc_std::RocRefcounted for DbRequest {
+    fn inc(&mut self) {
+        self.statements.inc();
+    }
+    fn dec(&mut self) {
+        self.statements.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+impl From<&DbRequest> for db::Request {
+    fn from(request: &DbRequest) -> Self {
+        db::Request {
+            id: request.id,
+            statements: request
+                .statements
+                .iter()
+                .map(|statement| db::Statement {
+                    sql: statement.sql.as_str().to_owned(),
+                    params: statement.params.iter().map(db::Value::from).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct DbQueryResult {
+    pub columns: RocList<RocStr>,
+    pub last_insert_id: i64,
+    pub rows: RocList<RocList<DbValue>>,
+    pub rows_affected: u64,
+}
+
+impl roc_std::RocRefcounted for DbQueryResult {
+    fn inc(&mut self) {
+        self.columns.inc();
+        self.rows.inc();
+    }
+    fn dec(&mut self) {
+        self.columns.dec();
+        self.rows.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct DbResponse {
+    pub error: roc_std::RocStr,
+    pub results: RocList<DbQueryResult>,
+    /// 0 when every statement succeeded, 1 when the app has no database and 2 for an error
+    /// from sqlite
+    pub failure: u8,
+}
+
+impl From<Result<Vec<db::QueryResult>, db::Error>> for DbResponse {
+    fn from(outcome: Result<Vec<db::QueryResult>, db::Error>) -> Self {
+        match outcome {
+            Ok(results) => DbResponse {
+                results: results
+                    .iter()
+                    .map(|result| DbQueryResult {
+                        columns: result
+                            .columns
+                            .iter()
+                            .map(|column| RocStr::from(column.as_str()))
+                            .collect(),
+                        last_insert_id: result.last_insert_id,
+                        rows: result
+                            .rows
+                            .iter()
+                            .map(|row| row.iter().map(DbValue::from).collect())
+                            .collect(),
+                        rows_affected: result.rows_affected,
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+            Err(db::Error::NoDatabase) => DbResponse {
+                failure: 1,
+                ..Default::default()
+            },
+            Err(db::Error::Sql(error)) => DbResponse {
+                failure: 2,
+                error: RocStr::from(error.as_str()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct HostJob {
+    pub initial_backoff_ms: u64,
+    pub name: roc_std::RocStr,
+    pub payload: RocList<u8>,
+    pub max_attempts: u32,
+}
+
+impl roc_std::RocRefcounted for HostJob {
+    fn inc(&mut self) {
+        self.name.inc();
+        self.payload.inc();
+    }
+    fn dec(&mut self) {
+        self.name.dec();
+        self.payload.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+impl From<&HostJob> for jobs::NewJob {
+    fn from(job: &HostJob) -> Self {
+        jobs::NewJob {
+            name: job.name.as_str().to_owned(),
+            payload: job.payload.as_slice().to_vec(),
+            max_attempts: job.max_attempts,
+            initial_backoff_ms: job.initial_backoff_ms,
+        }
+    }
+}
+
+/// The result of running a job once
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct JobOutcome {
+    pub error: roc_std::RocStr,
+    pub output: RocList<u8>,
+    /// 0 on success, 1 when the job failed and may be retried, 2 when its payload could not
+    /// be decoded and 3 when no job of that name is registered
+    pub failure: u8,
+}
+
+/// A job the host is done with, handed back to the roc backend
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct JobCompletion {
+    pub error: roc_std::RocStr,
+    pub name: roc_std::RocStr,
+    pub output: RocList<u8>,
+    pub payload: RocList<u8>,
+    pub attempts: u32,
+    pub failure: u8,
+}
+
+/// Settings the host reads from the roc backend on startup
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct BackendConfig {
+    /// File name of the app's database, empty when it does not use one
+    pub database: roc_std::RocStr,
+    /// Names of the registered jobs
+    pub jobs: RocList<RocStr>,
+    /// Whether the app wants to hear about clients connecting and disconnecting
+    pub presence: bool,
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct MetadataEntry {
+    pub name: roc_std::RocStr,
+    pub value: roc_std::RocStr,
+}
+
+impl roc_std::RocRefcounted for MetadataEntry {
+    fn inc(&mut self) {
+        self.name.inc();
+        self.value.inc();
+    }
+    fn dec(&mut self) {
+        self.name.dec();
+        self.value.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+/// A websocket or rpc request talking to the roc backend, as captured when it connected
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct Client {
+    pub accept_language: roc_std::RocStr,
+    pub address: roc_std::RocStr,
+    pub client_id: roc_std::RocStr,
+    /// Milliseconds since the unix epoch
+    pub connected_at_ms: u64,
+    /// Empty for anonymous clients
+    pub identity: roc_std::RocStr,
+    pub metadata: RocList<MetadataEntry>,
+    pub session_id: roc_std::RocStr,
+    pub user_agent: roc_std::RocStr,
+}
+
+impl roc_std::RocRefcounted for Client {
+    fn inc(&mut self) {
+        self.accept_language.inc();
+        self.address.inc();
+        self.client_id.inc();
+        self.identity.inc();
+        self.metadata.inc();
+        self.session_id.inc();
+        self.user_agent.inc();
+    }
+    fn dec(&mut self) {
+        self.accept_language.dec();
+        self.address.dec();
+        self.client_id.dec();
+        self.identity.dec();
+        self.metadata.dec();
+        self.session_id.dec();
+        self.user_agent.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+/// What the backend's `init!` is told about the host it is starting in
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct BackendStartup {
+    pub address: roc_std::RocStr,
+    pub build_version: roc_std::RocStr,
+    pub data_dir: roc_std::RocStr,
+    /// Milliseconds since the unix epoch
+    pub now_ms: u64,
+    pub restoring: bool,
+}
+
+/// An http request to the backend that is not for the frontend's static files
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct ServerRequest {
+    pub body: RocList<u8>,
+    pub headers: RocList<HttpHeader>,
+    pub method: roc_std::RocStr,
+    pub path: roc_std::RocStr,
+    pub query: RocList<HttpHeader>,
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct ServerResponse {
+    pub body: RocList<u8>,
+    pub headers: RocList<HttpHeader>,
+    pub status: u16,
+}
+
+impl roc_std::RocRefcounted for ServerResponse {
+    fn inc(&mut self) {
+        self.body.inc();
+        self.headers.inc();
+    }
+    fn dec(&mut self) {
+        self.body.dec();
+        self.headers.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+/// Work requested by the roc backend that the host carries out after an update
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct Effects {
+    pub db_requests: RocList<DbRequest>,
+    pub disconnects: RocList<Disconnect>,
+    pub http_requests: RocList<HttpRequest>,
+    pub jobs: RocList<HostJob>,
+    pub published: RocList<Published>,
+    pub to_frontend: RocList<ToFrontend>,
+    pub topics: RocList<TopicChange>,
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
 #[repr(C)]
 pub struct BackendUpdateReturn {
+    pub effects: Effects,
     pub model: RocBox<()>,
-    pub to_frontend: RocResult<ToFrontend, ()>,
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct BackendRouteReturn {
+    pub response: RocResult<ServerResponse, ()>,
+    pub update: BackendUpdateReturn,
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct BackendRpcReturn {
+    pub update: BackendUpdateReturn,
+    /// False when the posted bytes were not a valid `ToBackendMsg`
+    pub decoded: bool,
 }
 
 pub fn backend_update_for_host(
     model: Model,
-    client_id: RocStr,
-    session_id: RocStr,
+    client: Client,
     msg_bytes: RocStr,
 ) -> BackendUpdateReturn {
     extern "C" {
         fn roc__backend_update_for_host_1_exposed_generic(
             _: *mut BackendUpdateReturn,
             _: RocBox<()>,
-            _: &mut ManuallyDrop<roc_std::RocStr>,
-            _: &mut ManuallyDrop<roc_std::RocStr>,
+            _: &mut ManuallyDrop<Client>,
             _: &mut ManuallyDrop<roc_std::RocStr>,
         );
     }
@@ -77,8 +659,215 @@ pub fn backend_update_for_host(
         roc__backend_update_for_host_1_exposed_generic(
             ret.as_mut_ptr(),
             model.inner,
-            &mut ManuallyDrop::new(client_id),
-            &mut ManuallyDrop::new(session_id),
+            &mut ManuallyDrop::new(client),
+            &mut ManuallyDrop::new(msg_bytes),
+        );
+
+        ret.assume_init()
+    }
+}
+
+pub fn backend_http_response_for_host(
+    model: Model,
+    request_id: u64,
+    response: HttpResponse,
+) -> BackendUpdateReturn {
+    extern "C" {
+        fn roc__backend_http_response_for_host_1_exposed_generic(
+            _: *mut BackendUpdateReturn,
+            _: RocBox<()>,
+            _: u64,
+            _: &mut ManuallyDrop<HttpResponse>,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__backend_http_response_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            model.inner,
+            request_id,
+            &mut ManuallyDrop::new(response),
+        );
+
+        ret.assume_init()
+    }
+}
+
+pub fn backend_db_response_for_host(
+    model: Model,
+    request_id: u64,
+    response: DbResponse,
+) -> BackendUpdateReturn {
+    extern "C" {
+        fn roc__backend_db_response_for_host_1_exposed_generic(
+            _: *mut BackendUpdateReturn,
+            _: RocBox<()>,
+            _: u64,
+            _: &mut ManuallyDrop<DbResponse>,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__backend_db_response_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            model.inner,
+            request_id,
+            &mut ManuallyDrop::new(response),
+        );
+
+        ret.assume_init()
+    }
+}
+
+pub fn backend_config_for_host() -> BackendConfig {
+    extern "C" {
+        fn roc__backend_config_for_host_1_exposed_generic(_: *mut BackendConfig, _: i32);
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__backend_config_for_host_1_exposed_generic(ret.as_mut_ptr(), 0);
+
+        ret.assume_init()
+    }
+}
+
+/// Runs a job's roc code. This does not touch the model so it can be called from any
+/// thread while updates carry on
+pub fn backend_run_job_for_host(name: RocStr, payload: RocList<u8>) -> JobOutcome {
+    extern "C" {
+        fn roc__backend_run_job_for_host_1_exposed_generic(
+            _: *mut JobOutcome,
+            _: &mut ManuallyDrop<roc_std::RocStr>,
+            _: &mut ManuallyDrop<RocList<u8>>,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__backend_run_job_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            &mut ManuallyDrop::new(name),
+            &mut ManuallyDrop::new(payload),
+        );
+
+        ret.assume_init()
+    }
+}
+
+pub fn backend_init_for_host(startup: BackendStartup) -> BackendUpdateReturn {
+    extern "C" {
+        fn roc__backend_init_for_host_1_exposed_generic(
+            _: *mut BackendUpdateReturn,
+            _: &mut ManuallyDrop<BackendStartup>,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__backend_init_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            &mut ManuallyDrop::new(startup),
+        );
+
+        ret.assume_init()
+    }
+}
+
+pub fn backend_job_done_for_host(model: Model, completion: JobCompletion) -> BackendUpdateReturn {
+    extern "C" {
+        fn roc__backend_job_done_for_host_1_exposed_generic(
+            _: *mut BackendUpdateReturn,
+            _: RocBox<()>,
+            _: &mut ManuallyDrop<JobCompletion>,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__backend_job_done_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            model.inner,
+            &mut ManuallyDrop::new(completion),
+        );
+
+        ret.assume_init()
+    }
+}
+
+pub fn backend_presence_for_host(model: Model, clients: RocList<Client>) -> BackendUpdateReturn {
+    extern "C" {
+        fn roc__backend_presence_for_host_1_exposed_generic(
+            _: *mut BackendUpdateReturn,
+            _: RocBox<()>,
+            _: &mut ManuallyDrop<RocList<Client>>,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__backend_presence_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            model.inner,
+            &mut ManuallyDrop::new(clients),
+        );
+
+        ret.assume_init()
+    }
+}
+
+pub fn backend_route_for_host(model: Model, request: ServerRequest) -> BackendRouteReturn {
+    extern "C" {
+        fn roc__backend_route_for_host_1_exposed_generic(
+            _: *mut BackendRouteReturn,
+            _: RocBox<()>,
+            _: &mut ManuallyDrop<ServerRequest>,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__backend_route_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            model.inner,
+            &mut ManuallyDrop::new(request),
+        );
+
+        ret.assume_init()
+    }
+}
+
+pub fn backend_rpc_for_host(
+    model: Model,
+    client: Client,
+    msg_bytes: RocList<u8>,
+) -> BackendRpcReturn {
+    extern "C" {
+        fn roc__backend_rpc_for_host_1_exposed_generic(
+            _: *mut BackendRpcReturn,
+            _: RocBox<()>,
+            _: &mut ManuallyDrop<Client>,
+            _: &mut ManuallyDrop<RocList<u8>>,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__backend_rpc_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            model.inner,
+            &mut ManuallyDrop::new(client),
             &mut ManuallyDrop::new(msg_bytes),
         );
 
@@ -101,16 +890,10 @@ pub extern "C" fn roc_fx_send_to_frontend_impl(client_id: &RocStr, msg: &RocStr)
     let Some(runtime) = ASYNC_RUNTIME.get() else {
         return;
     };
-    let client_id = client_id.as_str().to_owned();
-    let msg_bytes = msg.as_str().to_owned();
+    let message = MessageInfo::new(client_id.as_str().to_owned(), 0, msg.as_str());
 
     runtime.spawn(async {
-        _ = tx
-            .send(MessageInfo {
-                client_id,
-                msg_bytes,
-            })
-            .await;
+        _ = tx.send(message.into()).await;
     });
 }
 
@@ -134,8 +917,10 @@ pub unsafe extern "C" fn roc_dealloc(c_ptr: *mut c_void, _alignment: u32) {
     libc::free(c_ptr);
 }
 
+/// The panic unwinds back into the host, which drops the update that crashed and carries on
+/// with the model it had before
 #[no_mangle]
-pub unsafe extern "C" fn roc_panic(msg: *mut RocStr, tag_id: u32) {
+pub unsafe extern "C-unwind" fn roc_panic(msg: *mut RocStr, tag_id: u32) {
     match tag_id {
         0 => {
             eprintln!("Roc standard library hit a panic: {}", &*msg);
@@ -151,7 +936,52 @@ pub unsafe extern "C" fn roc_panic(msg: *mut RocStr, tag_id: u32) {
 
 #[no_mangle]
 pub unsafe extern "C" fn roc_dbg(loc: *mut RocStr, msg: *mut RocStr, src: *mut RocStr) {
-    eprintln!("[{}] {} = {}", &*loc, &*src, &*msg);
+    tracing::debug!(
+        target: "app",
+        location = (*loc).as_str(),
+        "{} = {}",
+        (*src).as_str(),
+        (*msg).as_str()
+    );
+}
+
+/// Backs `Log.debug!` and friends. Events are emitted inside the span of whatever the host
+/// is handling, so they carry its client id, session id and message. Tracing only knows
+/// field names at compile time, so the app's pairs are recorded together as `fields`
+#[no_mangle]
+pub extern "C" fn roc_fx_log(level: u8, message: &RocStr, fields: &RocList<LogField>) {
+    let message = message.as_str();
+    let fields = (!fields.is_empty()).then(|| tracing::field::display(LogFields(fields)));
+    match level {
+        0 => tracing::debug!(target: "app", fields, "{message}"),
+        1 => tracing::info!(target: "app", fields, "{message}"),
+        2 => tracing::warn!(target: "app", fields, "{message}"),
+        _ => tracing::error!(target: "app", fields, "{message}"),
+    }
+}
+
+/// Writes log fields as `key="value"` pairs
+struct LogFields<'a>(&'a RocList<LogField>);
+
+impl std::fmt::Display for LogFields<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, field) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}={:?}", field.f0.as_str(), field.f1.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+/// Backs `Env.var!`
+#[no_mangle]
+pub extern "C" fn roc_fx_env_var(name: &RocStr) -> RocResult<RocStr, ()> {
+    match secrets::var(name.as_str()) {
+        Some(value) => RocResult::ok(value.as_str().into()),
+        None => RocResult::err(()),
+    }
 }
 
 #[no_mangle]
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::{env, fs, io};
+
+static SECRETS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Where the secrets file is read from, `secrets.env` in the working directory unless
+/// `GALENA_SECRETS_FILE` says otherwise
+pub fn path() -> PathBuf {
+    env::var("GALENA_SECRETS_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("secrets.env"))
+}
+
+/// Loads `KEY=VALUE` lines from the secrets file. A missing file is only an error when it
+/// was asked for explicitly. The file is refused if it sits inside the directory served to
+/// browsers, since everything in there is public
+pub fn load(path: &Path, dist_dir: &Path) -> anyhow::Result<usize> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err)
+            if err.kind() == io::ErrorKind::NotFound
+                && env::var_os("GALENA_SECRETS_FILE").is_none() =>
+        {
+            String::new()
+        }
+        Err(err) => return Err(anyhow::anyhow!("Could not read {}: {err}", path.display())),
+    };
+
+    if !contents.is_empty() {
+        if let (Ok(path), Ok(dist_dir)) = (fs::canonicalize(path), fs::canonicalize(dist_dir)) {
+            anyhow::ensure!(
+                !path.starts_with(&dist_dir),
+                "The secrets file {} is inside DIST_DIR and would be served publicly",
+                path.display()
+            );
+        }
+    }
+
+    let secrets = parse(&contents)?;
+    let count = secrets.len();
+    SECRETS
+        .set(secrets)
+        .map_err(|_| anyhow::anyhow!("Secrets are already loaded"))?;
+
+    Ok(count)
+}
+
+fn parse(contents: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut secrets = HashMap::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Line {} of the secrets file has no `=`", index + 1))?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        secrets.insert(key.trim().to_owned(), value.to_owned());
+    }
+
+    Ok(secrets)
+}
+
+/// Looks a variable up in the process environment, then in the secrets file
+pub fn var(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .or_else(|| SECRETS.get()?.get(name).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own for the test, removed when it is dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                env::temp_dir().join(format!("galena-secrets-{}-{name}", std::process::id()));
+            _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(path.join("dist")).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let secrets =
+            parse("# Stripe\n\n  \nSTRIPE_KEY=sk_test\n  # old: STRIPE_KEY=sk_live\n").unwrap();
+
+        assert_eq!(secrets.len(), 1);
+        assert_eq!(secrets["STRIPE_KEY"], "sk_test");
+    }
+
+    #[test]
+    fn keys_and_values_are_trimmed_and_unquoted() {
+        let secrets =
+            parse(" API_KEY = abc \nPHRASE=\"two words \"\nHALF=\"open\nEQUALS=a=b\nEMPTY=\n")
+                .unwrap();
+
+        assert_eq!(secrets["API_KEY"], "abc");
+        assert_eq!(secrets["PHRASE"], "two words ");
+        assert_eq!(secrets["HALF"], "\"open");
+        assert_eq!(secrets["EQUALS"], "a=b");
+        assert_eq!(secrets["EMPTY"], "");
+    }
+
+    #[test]
+    fn lines_without_equals_are_refused() {
+        let err = parse("API_KEY=abc\n\nAPI_SECRET\n").unwrap_err();
+
+        assert_eq!(err.to_string(), "Line 3 of the secrets file has no `=`");
+    }
+
+    #[test]
+    fn secrets_inside_the_dist_dir_are_refused() {
+        let dir = TempDir::new("inside");
+        let path = dir.0.join("dist/secrets.env");
+        fs::write(&path, "API_KEY=abc\n").unwrap();
+
+        let err = load(&path, &dir.0.join("dist")).unwrap_err();
+
+        assert!(err.to_string().contains("inside DIST_DIR"), "{err}");
+        assert!(SECRETS.get().is_none());
+    }
+}
@@ -1,13 +1,21 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::env;
-use std::net::SocketAddr;
-use std::path::Path;
-use std::sync::{Arc, RwLock};
-
-use axum::extract::ws::{Message, WebSocket};
-use axum::extract::{ConnectInfo, State, WebSocketUpgrade};
-use axum::response::IntoResponse;
-use axum::routing::{any, get_service};
+use std::fmt::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::body::{Body, Bytes};
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use axum::extract::{ConnectInfo, Request, State, WebSocketUpgrade};
+use axum::http::header::{ACCEPT_LANGUAGE, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{any, get, get_service, post};
 use axum::Router;
 use base64::alphabet::STANDARD;
 use base64::engine::{GeneralPurpose, GeneralPurposeConfig};
@@ -17,9 +25,10 @@ use futures::stream::SplitSink;
 use futures::{SinkExt, StreamExt};
 use mime;
 use rand::{self, RngCore};
-use roc_std::RocStr;
+use roc_std::{RocList, RocStr};
 use tokio::net::TcpListener;
 use tokio::sync::{mpsc, Mutex};
+use tower::ServiceExt;
 use tower_cookies::{CookieManagerLayer, Cookies};
 use tower_http::{
     services::{ServeDir, ServeFile},
@@ -29,35 +38,613 @@ use tracing::{debug, error, info, instrument, warn};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-use crate::roc::{self, call_roc_backend_init, BackendUpdateReturn, Model, ToFrontend};
-use crate::{MessageInfo, CHANNEL_SENDER};
+use crate::roc::{
+    self, BackendRouteReturn, BackendRpcReturn, BackendStartup, BackendUpdateReturn, Client,
+    DbResponse, Effects, HttpHeader, HttpResponse, JobCompletion, MetadataEntry, Model,
+    ServerRequest, ServerResponse,
+};
+use crate::{db, http, jobs, secrets, CloseTarget, MessageInfo, Outgoing, CHANNEL_SENDER};
+
+const ADDRESS: &str = "0.0.0.0:3000";
+
+static TOPICS: OnceLock<std::sync::Mutex<Topics>> = OnceLock::new();
+
+/// Which clients are subscribed to which topics. Kept in both directions so that a client
+/// that disconnects can be pruned without going through every topic
+#[derive(Debug, Default)]
+struct Topics {
+    subscribers: HashMap<String, HashSet<String>>,
+    subscriptions: HashMap<String, HashSet<String>>,
+}
+
+impl Topics {
+    fn subscribe(&mut self, client_id: &str, topic: &str) {
+        self.subscribers
+            .entry(topic.to_owned())
+            .or_default()
+            .insert(client_id.to_owned());
+        self.subscriptions
+            .entry(client_id.to_owned())
+            .or_default()
+            .insert(topic.to_owned());
+    }
+
+    fn unsubscribe(&mut self, client_id: &str, topic: &str) {
+        if let Some(subscribers) = self.subscribers.get_mut(topic) {
+            subscribers.remove(client_id);
+            if subscribers.is_empty() {
+                self.subscribers.remove(topic);
+            }
+        }
+        if let Some(subscriptions) = self.subscriptions.get_mut(client_id) {
+            subscriptions.remove(topic);
+            if subscriptions.is_empty() {
+                self.subscriptions.remove(client_id);
+            }
+        }
+    }
+
+    fn remove_client(&mut self, client_id: &str) {
+        for topic in self.subscriptions.remove(client_id).unwrap_or_default() {
+            if let Some(subscribers) = self.subscribers.get_mut(&topic) {
+                subscribers.remove(client_id);
+                if subscribers.is_empty() {
+                    self.subscribers.remove(&topic);
+                }
+            }
+        }
+    }
+}
+
+fn topics() -> std::sync::MutexGuard<'static, Topics> {
+    TOPICS
+        .get_or_init(Default::default)
+        .lock()
+        .expect("Could not acquire topics lock")
+}
+
+/// Largest request body that is handed to the roc backend's routes
+const MAX_ROUTE_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+/// Number of job workers unless `GALENA_JOB_WORKERS` says otherwise
+const DEFAULT_JOB_WORKERS: usize = 4;
+
+/// Time between websocket pings unless `GALENA_HEARTBEAT_INTERVAL_MS` says otherwise
+const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Pings in a row a client may leave unanswered unless `GALENA_HEARTBEAT_MISSES` says otherwise
+const DEFAULT_HEARTBEAT_MISSES: u32 = 3;
+
+/// Header that lets clients of the rpc endpoint pick the session id their messages are
+/// attributed to
+const RPC_SESSION_HEADER: &str = "x-galena-session";
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// How long the id of the last message a client sent is remembered after its last message,
+/// so that a frontend which reconnects within that time does not have its outbox run twice
+const DELIVERED_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// Longest client id a frontend may ask to keep across reconnects
+const MAX_CLIENT_ID_LEN: usize = 64;
+
+/// Sent when a socket is replaced by another one with the same client id. A frontend that
+/// reconnected no longer listens to its old socket, so only a duplicated tab, which shares
+/// the id through sessionStorage, sees this. The code is final, so that the two tabs do not
+/// take the id from each other until they give up reconnecting
+const REPLACED_CLOSE_CODE: u16 = 1000;
+const REPLACED_CLOSE_REASON: &str = "This page was opened again in another tab";
+
+/// Tells the sockets of a client apart, so that the loop of a socket that was replaced by a
+/// reconnect does not clean up after the new one
+static SOCKET_GENERATION: AtomicU64 = AtomicU64::new(0);
 
 #[derive(Debug, Clone)]
 struct AppState {
-    clients: Arc<Mutex<HashMap<String, SplitSink<WebSocket, Message>>>>,
+    clients: Arc<Mutex<HashMap<String, Socket>>>,
     roc_model: Arc<RwLock<Model>>,
+    static_files: ServeDir,
+    /// Bearer token required by `POST /_galena/msg`, the endpoint is disabled without one
+    rpc_token: Option<Arc<str>>,
+    presence: Presence,
+    client_ids: ClientIds,
+    delivered: Delivered,
+    trusted_proxies: TrustedProxies,
+    heartbeat: Heartbeat,
+}
+
+/// How often websockets are pinged and how many unanswered pings make a client count as
+/// gone. Without this half-open connections would never be noticed
+#[derive(Debug, Clone, Copy)]
+struct Heartbeat {
+    interval: Duration,
+    max_missed: u32,
+}
+
+impl Heartbeat {
+    fn from_env() -> Self {
+        let interval = env::var("GALENA_HEARTBEAT_INTERVAL_MS")
+            .ok()
+            .and_then(|interval| interval.parse().ok())
+            .filter(|&interval| interval > 0)
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL);
+        let max_missed = env::var("GALENA_HEARTBEAT_MISSES")
+            .ok()
+            .and_then(|misses| misses.parse().ok())
+            .filter(|&misses| misses > 0)
+            .unwrap_or(DEFAULT_HEARTBEAT_MISSES);
+
+        Heartbeat {
+            interval,
+            max_missed,
+        }
+    }
+}
+
+/// The pings sent to one client that were not answered yet, oldest first
+#[derive(Debug, Default)]
+struct Pings {
+    sequence: u64,
+    outstanding: VecDeque<(u64, tokio::time::Instant)>,
+    /// Ticks in a row at which the previous ping was still unanswered
+    missed: u32,
+}
+
+impl Pings {
+    /// Records a new ping, returning its sequence number and how many pings in a row are
+    /// now missed. A ping only counts as missed once the next one is due and it is still
+    /// unanswered, so a pong that is just slow does not count against the client
+    fn tick(&mut self, now: tokio::time::Instant, heartbeat: &Heartbeat) -> (u64, u32) {
+        if !self.outstanding.is_empty() {
+            self.missed += 1;
+        }
+        self.sequence += 1;
+        self.outstanding.push_back((self.sequence, now));
+        // Older pings only matter as long as the client could still be let off for them
+        while self.outstanding.len() > heartbeat.max_missed as usize {
+            self.outstanding.pop_front();
+        }
+
+        (self.sequence, self.missed)
+    }
+
+    /// The round-trip time of the ping a pong answers. A pong that arrives after the next
+    /// ping was sent still counts, and answers every ping before it too
+    fn pong(&mut self, payload: &[u8], now: tokio::time::Instant) -> Option<Duration> {
+        let sequence = u64::from_be_bytes(payload.try_into().ok()?);
+        let answered = self
+            .outstanding
+            .iter()
+            .position(|(pending, _)| *pending == sequence)?;
+        let (_, sent_at) = self.outstanding[answered];
+        self.outstanding.drain(..=answered);
+        self.missed = 0;
+
+        Some(now.saturating_duration_since(sent_at))
+    }
+}
+
+/// The sending half of a client's websocket
+#[derive(Debug)]
+struct Socket {
+    session_id: String,
+    generation: u64,
+    sink: SplitSink<WebSocket, Message>,
+}
+
+/// Reverse proxies in front of the host whose `X-Forwarded-For` and identity headers are
+/// believed. Both are ignored on connections coming from anywhere else, since clients can
+/// set them to anything
+#[derive(Debug, Clone, Default)]
+struct TrustedProxies {
+    /// From the comma separated `GALENA_TRUSTED_PROXIES`
+    addresses: Arc<[IpAddr]>,
+    /// Header the proxies put the authenticated user in, from `GALENA_IDENTITY_HEADER`
+    identity_header: Option<HeaderName>,
+}
+
+impl TrustedProxies {
+    fn from_env() -> Self {
+        let addresses = env::var("GALENA_TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .filter_map(|address| match address.parse() {
+                Ok(address) => Some(address),
+                Err(_) => {
+                    warn!(address, "Ignoring invalid trusted proxy address");
+                    None
+                }
+            })
+            .collect();
+        let identity_header = env::var("GALENA_IDENTITY_HEADER")
+            .ok()
+            .and_then(|header| HeaderName::try_from(header).ok());
+
+        TrustedProxies {
+            addresses,
+            identity_header,
+        }
+    }
+
+    fn trusts(&self, address: &IpAddr) -> bool {
+        self.addresses.contains(address)
+    }
+
+    /// Walks `X-Forwarded-For` from the right, as long as the hops are trusted proxies,
+    /// and returns the first address that is not one of them
+    fn client_address(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.trusts(&peer) {
+            return peer;
+        }
+
+        let forwarded = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        let mut address = peer;
+        for hop in forwarded.into_iter().rev() {
+            let Ok(hop) = hop.parse() else {
+                break;
+            };
+            address = hop;
+            if !self.trusts(&address) {
+                break;
+            }
+        }
+
+        address
+    }
+
+    fn identity(&self, peer: IpAddr, headers: &HeaderMap) -> Option<String> {
+        if !self.trusts(&peer) {
+            return None;
+        }
+
+        self.identity_header
+            .as_ref()
+            .and_then(|header| headers.get(header))
+            .and_then(|value| value.to_str().ok())
+            .filter(|identity| !identity.is_empty())
+            .map(str::to_owned)
+    }
+}
+
+/// The session every client id was issued to. A frontend may only keep its id across
+/// reconnects within the same session, so other sessions cannot take over a client by
+/// asking for its id. Ids stay bound while they are connected and for as long as their
+/// delivered messages are remembered afterwards
+#[derive(Debug, Clone, Default)]
+struct ClientIds {
+    sessions: Arc<std::sync::Mutex<HashMap<String, IssuedTo>>>,
+}
+
+#[derive(Debug)]
+struct IssuedTo {
+    session_id: String,
+    /// When the last socket of the client closed, `None` while it is connected
+    closed_at: Option<tokio::time::Instant>,
+}
+
+impl ClientIds {
+    /// The requested id when it is free or already bound to the session, a new one otherwise
+    fn claim(&self, requested: Option<String>, session_id: &str) -> String {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .expect("Could not acquire client ids lock");
+        sessions.retain(|_, issued| {
+            issued
+                .closed_at
+                .map_or(true, |closed_at| closed_at.elapsed() < DELIVERED_RETENTION)
+        });
+
+        let client_id = match requested {
+            Some(client_id)
+                if sessions
+                    .get(&client_id)
+                    .map_or(true, |issued| issued.session_id == session_id) =>
+            {
+                client_id
+            }
+            Some(client_id) => {
+                warn!(?client_id, "Client id requested by another session");
+                random_client_id()
+            }
+            None => random_client_id(),
+        };
+        sessions.insert(
+            client_id.clone(),
+            IssuedTo {
+                session_id: session_id.to_owned(),
+                closed_at: None,
+            },
+        );
+
+        client_id
+    }
+
+    /// Starts the time after which the id may be issued to another session
+    fn release(&self, client_id: &str) {
+        if let Some(issued) = self
+            .sessions
+            .lock()
+            .expect("Could not acquire client ids lock")
+            .get_mut(client_id)
+        {
+            issued.closed_at = Some(tokio::time::Instant::now());
+        }
+    }
+}
+
+/// The id of the last message each client sent. The frontend numbers its messages and sends
+/// those that were not acknowledged again after reconnecting, which may include some that
+/// arrived just before the connection dropped. Clients are told apart by their session too,
+/// so a client id that ends up with another session starts counting afresh
+#[derive(Debug, Clone, Default)]
+struct Delivered {
+    last_ids: Arc<std::sync::Mutex<HashMap<DeliveredKey, (u64, tokio::time::Instant)>>>,
+}
+
+/// The session id and client id of a client
+type DeliveredKey = (String, String);
+
+impl Delivered {
+    /// Returns whether the message is new, along with the id to acknowledge
+    fn record(&self, session_id: &str, client_id: &str, message_id: u64) -> (bool, u64) {
+        let mut last_ids = self
+            .last_ids
+            .lock()
+            .expect("Could not acquire delivered lock");
+        let now = tokio::time::Instant::now();
+
+        let key = (session_id.to_owned(), client_id.to_owned());
+        match last_ids.get_mut(&key) {
+            Some((last_id, _)) if message_id <= *last_id => (false, *last_id),
+            Some(last) => {
+                *last = (message_id, now);
+                (true, message_id)
+            }
+            None => {
+                last_ids.insert(key, (message_id, now));
+                (true, message_id)
+            }
+        }
+    }
+
+    fn prune(&self) {
+        self.last_ids
+            .lock()
+            .expect("Could not acquire delivered lock")
+            .retain(|_, (_, seen_at)| seen_at.elapsed() < DELIVERED_RETENTION);
+    }
+}
+
+/// Splits the id the frontend numbers its messages with off a frame, leaving the
+/// `{"request_id":…,"msg":…}` the roc backend decodes
+fn split_message_id(frame: &str) -> (Option<u64>, Cow<'_, str>) {
+    let Some((message_id, rest)) = frame
+        .strip_prefix(r#"{"message_id":"#)
+        .and_then(|rest| rest.split_once(','))
+    else {
+        return (None, Cow::Borrowed(frame));
+    };
+
+    match message_id.parse() {
+        Ok(message_id) => (Some(message_id), Cow::Owned(format!("{{{rest}"))),
+        Err(_) => (None, Cow::Borrowed(frame)),
+    }
+}
+
+/// The websockets that are currently connected. The roc backend is sent the full list
+/// whenever a client connects or disconnects, if it registered a presence handler
+#[derive(Debug, Clone, Default)]
+struct Presence {
+    connections: Arc<std::sync::Mutex<HashMap<String, Connection>>>,
+    notify: bool,
+}
+
+/// What the host knows about a client, captured when it connected
+#[derive(Debug, Clone)]
+struct Connection {
+    session_id: String,
+    address: IpAddr,
+    user_agent: String,
+    accept_language: String,
+    identity: Option<String>,
+    connected_at_ms: u64,
+    metadata: BTreeMap<String, String>,
+}
+
+impl Connection {
+    fn capture(
+        session_id: String,
+        peer: SocketAddr,
+        headers: &HeaderMap,
+        trusted_proxies: &TrustedProxies,
+    ) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_owned()
+        };
+
+        Connection {
+            session_id,
+            address: trusted_proxies.client_address(peer.ip(), headers),
+            user_agent: header(USER_AGENT),
+            accept_language: header(ACCEPT_LANGUAGE),
+            identity: trusted_proxies.identity(peer.ip(), headers),
+            connected_at_ms: now_ms(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    fn to_roc(&self, client_id: &str) -> Client {
+        Client {
+            accept_language: self.accept_language.as_str().into(),
+            address: self.address.to_string().as_str().into(),
+            client_id: client_id.into(),
+            connected_at_ms: self.connected_at_ms,
+            identity: self.identity.as_deref().unwrap_or_default().into(),
+            metadata: self
+                .metadata
+                .iter()
+                .map(|(name, value)| MetadataEntry {
+                    name: name.as_str().into(),
+                    value: value.as_str().into(),
+                })
+                .collect(),
+            session_id: self.session_id.as_str().into(),
+            user_agent: self.user_agent.as_str().into(),
+        }
+    }
+}
+
+impl Presence {
+    fn connect(&self, model: &Arc<RwLock<Model>>, client_id: &str, connection: Connection) {
+        self.connections
+            .lock()
+            .expect("Could not acquire presence lock")
+            .insert(client_id.to_owned(), connection);
+        self.notify(model);
+    }
+
+    /// Updates what is known about a client without telling the backend, the new value is
+    /// part of the next list it gets
+    fn set_metadata(&self, client_id: &str, name: &str, value: String) {
+        if let Some(connection) = self
+            .connections
+            .lock()
+            .expect("Could not acquire presence lock")
+            .get_mut(client_id)
+        {
+            connection.metadata.insert(name.to_owned(), value);
+        }
+    }
+
+    fn disconnect(&self, model: &Arc<RwLock<Model>>, client_id: &str) {
+        let removed = self
+            .connections
+            .lock()
+            .expect("Could not acquire presence lock")
+            .remove(client_id);
+        if removed.is_some() {
+            self.notify(model);
+        }
+    }
+
+    /// The list is taken while the model is locked, so the backend always ends up with the
+    /// latest one even when clients come and go concurrently
+    fn notify(&self, model: &Arc<RwLock<Model>>) {
+        if !self.notify {
+            return;
+        }
+
+        update_model(model, |roc_model| {
+            roc::backend_presence_for_host(roc_model, self.to_roc())
+        });
+    }
+
+    fn metrics(&self) -> String {
+        let connections = self
+            .connections
+            .lock()
+            .expect("Could not acquire presence lock");
+        let mut rtts = connections
+            .iter()
+            .filter_map(|(client_id, connection)| {
+                Some((client_id, connection.metadata.get("rtt_ms")?))
+            })
+            .collect::<Vec<_>>();
+        rtts.sort_unstable();
+
+        let mut metrics = format!(
+            "# HELP galena_connected_clients Websockets that are connected\n\
+            # TYPE galena_connected_clients gauge\n\
+            galena_connected_clients {}\n\
+            # HELP galena_client_rtt_ms Round-trip time of the last answered ping of a client\n\
+            # TYPE galena_client_rtt_ms gauge\n",
+            connections.len()
+        );
+        for (client_id, rtt_ms) in rtts {
+            _ = writeln!(
+                metrics,
+                "galena_client_rtt_ms{{client_id=\"{client_id}\"}} {rtt_ms}"
+            );
+        }
+
+        metrics
+    }
+
+    fn to_roc(&self) -> RocList<Client> {
+        let connections = self
+            .connections
+            .lock()
+            .expect("Could not acquire presence lock");
+        let mut clients = connections
+            .iter()
+            .map(|(client_id, connection)| connection.to_roc(client_id))
+            .collect::<Vec<_>>();
+        clients.sort_by(|a, b| {
+            (a.connected_at_ms, &a.client_id).cmp(&(b.connected_at_ms, &b.client_id))
+        });
+
+        clients.into_iter().collect()
+    }
 }
 
 pub async fn run_server() {
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-                format!("{}=debug,tower_http=debug", env!("CARGO_CRATE_NAME")).into()
+                format!(
+                    "{}=debug,app=debug,tower_http=debug",
+                    env!("CARGO_CRATE_NAME")
+                )
+                .into()
             }),
         )
         .with(tracing_subscriber::fmt::layer())
         .init();
 
     let dist_dir = env::var("DIST_DIR").expect("DIST_DIR is not defined");
+    let rpc_token = env::var("GALENA_RPC_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .map(Arc::from);
+    if rpc_token.is_none() {
+        info!("GALENA_RPC_TOKEN is not set, POST /_galena/msg is disabled");
+    }
 
-    debug!("Initializing roc model");
-    let roc_model = unsafe {
-        let boxed_model = call_roc_backend_init();
-        roc::Model::init(boxed_model)
-    };
+    let secrets_path = secrets::path();
+    let secrets =
+        secrets::load(&secrets_path, Path::new(&dist_dir)).expect("Could not load secrets");
+    if secrets > 0 {
+        info!(path = ?secrets_path, secrets, "Loaded secrets");
+    }
 
-    let clients: Arc<Mutex<HashMap<String, SplitSink<WebSocket, Message>>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+    let config = roc::backend_config_for_host();
+    if !config.database.is_empty() {
+        let path = data_dir().join(config.database.as_str());
+        db::open(&path).expect("Could not open database");
+        info!(?path, "Opened database");
+    }
+    let has_jobs = !config.jobs.is_empty();
+    if has_jobs {
+        let path = data_dir().join("jobs.sqlite");
+        jobs::open(&path).expect("Could not open job queue");
+        info!(?path, jobs = ?config.jobs, "Opened job queue");
+    }
+
+    let clients: Arc<Mutex<HashMap<String, Socket>>> = Arc::new(Mutex::new(HashMap::new()));
 
     debug!("Initializing sender channel");
     let (tx, mut rx) = mpsc::channel(20);
@@ -66,33 +653,74 @@ pub async fn run_server() {
     {
         let clients = Arc::clone(&clients);
         tokio::spawn(async move {
-            while let Some(MessageInfo {
-                client_id,
-                msg_bytes,
-            }) = rx.recv().await
-            {
-                debug!(?client_id, "Receive channel message");
+            while let Some(outgoing) = rx.recv().await {
                 let mut clients = clients.lock().await;
-                match clients.get_mut(&client_id) {
-                    Some(sink) => {
-                        debug!(?sink, "Sink");
-                        sink.send(Message::Text(msg_bytes))
-                            .await
-                            .unwrap_or_else(|_| {
-                                error!("Could not send message through websocket");
-                            });
+                match outgoing {
+                    Outgoing::Message(MessageInfo {
+                        client_id,
+                        msg_bytes,
+                    }) => {
+                        debug!(?client_id, "Receive channel message");
+                        match clients.get_mut(&client_id) {
+                            Some(socket) => {
+                                socket
+                                    .sink
+                                    .send(Message::Text(msg_bytes))
+                                    .await
+                                    .unwrap_or_else(|_| {
+                                        error!("Could not send message through websocket");
+                                    });
+                            }
+                            _ => {
+                                error!(
+                                    clients = ?clients.keys(),
+                                    "Client id not in connected clients"
+                                );
+                            }
+                        }
                     }
-                    _ => {
-                        error!(
-                            clients = ?clients.iter(),
-                            "Client id not in connected clients"
-                        );
+                    Outgoing::Close {
+                        target,
+                        code,
+                        reason,
+                    } => {
+                        // The sockets are removed once the clients acknowledge the close
+                        for (client_id, socket) in clients.iter_mut() {
+                            let matches = match &target {
+                                CloseTarget::Client(id) => id == client_id,
+                                CloseTarget::Session(id) => *id == socket.session_id,
+                            };
+                            if !matches {
+                                continue;
+                            }
+
+                            info!(?client_id, code, reason, "Closing websocket");
+                            let frame = CloseFrame {
+                                code,
+                                reason: reason.clone().into(),
+                            };
+                            socket
+                                .sink
+                                .send(Message::Close(Some(frame)))
+                                .await
+                                .unwrap_or_else(|_| {
+                                    error!("Could not close websocket");
+                                });
+                        }
                     }
                 }
             }
         });
     }
 
+    debug!("Initializing roc model");
+    let BackendUpdateReturn { model, effects } = roc::backend_init_for_host(startup());
+    let roc_model = Arc::new(RwLock::new(unsafe { roc::Model::init(model) }));
+    run_effects(&roc_model, effects);
+    if has_jobs {
+        spawn_job_workers(&roc_model);
+    }
+
     let router = Router::new()
         .route(
             "/",
@@ -102,15 +730,27 @@ pub async fn run_server() {
             )),
         )
         .route("/ws", any(ws_handler))
-        .fallback_service(ServeDir::new(dist_dir))
+        .route("/_galena/msg", post(rpc_handler))
+        .route("/_galena/metrics", get(metrics_handler))
+        .fallback(route_handler)
         .layer(CookieManagerLayer::new())
         .layer(TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::default()))
         .with_state(AppState {
             clients,
-            roc_model: Arc::new(RwLock::new(roc_model)),
+            roc_model,
+            static_files: ServeDir::new(dist_dir),
+            rpc_token,
+            presence: Presence {
+                notify: config.presence,
+                ..Presence::default()
+            },
+            client_ids: ClientIds::default(),
+            delivered: Delivered::default(),
+            trusted_proxies: TrustedProxies::from_env(),
+            heartbeat: Heartbeat::from_env(),
         });
 
-    let listener = TcpListener::bind("0.0.0.0:3000")
+    let listener = TcpListener::bind(ADDRESS)
         .await
         .expect("Unable to bind to port");
     info!("listening on {}", listener.local_addr().unwrap());
@@ -128,6 +768,8 @@ async fn ws_handler(
     State(state): State<AppState>,
     ws: WebSocketUpgrade,
     cookies: Cookies,
+    headers: HeaderMap,
+    uri: Uri,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
 ) -> impl IntoResponse {
     info!("Websocket connection requested");
@@ -135,111 +777,1201 @@ async fn ws_handler(
     let session_id = match cookies.get("sessionid") {
         Some(sid) => sid.value().to_string(),
         _ => {
-            let mut sid_buf = [0u8; 16];
-
-            // TODO: Move the random number generator and base64 engine to a shared location for
-            // each request
-            let mut rng = rand::thread_rng();
-            rng.fill_bytes(&mut sid_buf);
-            let engine = GeneralPurpose::new(&STANDARD, GeneralPurposeConfig::default());
-            let sesssion_id = engine.encode(sid_buf);
+            let sesssion_id = random_id();
             cookies.add(Cookie::new("sessionid", sesssion_id.clone()));
 
             sesssion_id
         }
     };
 
-    let client_id =
-        GeneralPurpose::new(&STANDARD, GeneralPurposeConfig::default()).encode(&addr.to_string());
+    // The frontend asks to keep its client id when it reconnects
+    let requested_client_id = uri.query().and_then(|query| {
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(name, _)| name == "client_id")
+            .map(|(_, value)| value.into_owned())
+            .filter(|client_id| valid_client_id(client_id))
+    });
+
+    let connection = Connection::capture(session_id, addr, &headers, &state.trusted_proxies);
+
+    ws.on_upgrade(move |socket| {
+        handle_websocket_connection(state, socket, requested_client_id, connection)
+    })
+}
+
+fn valid_client_id(client_id: &str) -> bool {
+    (1..=MAX_CLIENT_ID_LEN).contains(&client_id.len())
+        && client_id
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+/// Lets scripts and other services send a `ToBackendMsg` without opening a websocket. The
+/// message runs through `update_from_frontend` under a client id made up for the request and
+/// any `ToFrontendMsg` that update sends to that client is returned as a JSON array
+#[instrument(skip_all)]
+async fn rpc_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(status) = authorize_rpc(state.rpc_token.as_deref(), &headers) {
+        return status.into_response();
+    }
+
+    let client_id = format!("rpc:{}", random_id());
+    let session_id = headers
+        .get(RPC_SESSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map_or_else(|| client_id.clone(), str::to_owned);
+    debug!(?client_id, ?session_id, "Received rpc message");
+    let client =
+        Connection::capture(session_id, addr, &headers, &state.trusted_proxies).to_roc(&client_id);
+
+    let replies = update_model_with(&state.roc_model, |roc_model| {
+        let BackendRpcReturn { update, decoded } =
+            roc::backend_rpc_for_host(roc_model, client, RocList::from_slice(&body));
+
+        // Messages for the rpc client are answered here instead of going to a websocket
+        let (replies, to_frontend): (Vec<_>, Vec<_>) = update
+            .effects
+            .to_frontend
+            .iter()
+            .cloned()
+            .partition(|message| message.client_id.as_str() == client_id);
+        let update = BackendUpdateReturn {
+            effects: Effects {
+                to_frontend: to_frontend.into_iter().collect(),
+                ..update.effects
+            },
+            model: update.model,
+        };
+
+        (
+            update,
+            decoded.then(|| {
+                replies
+                    .iter()
+                    .map(|reply| reply.message.as_str().to_owned())
+                    .collect::<Vec<_>>()
+            }),
+        )
+    });
+    // The rpc client is gone once this request is answered
+    topics().remove_client(&client_id);
+
+    match replies {
+        Some(Some(replies)) => (
+            [(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())],
+            format!("[{}]", replies.join(",")),
+        )
+            .into_response(),
+        Some(None) => (StatusCode::BAD_REQUEST, "Body is not a valid ToBackendMsg").into_response(),
+        None => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Checks the bearer token of a request to one of the `/_galena` endpoints, which are not
+/// there at all without a token
+fn authorize_rpc(expected_token: Option<&str>, headers: &HeaderMap) -> Result<(), StatusCode> {
+    let Some(expected_token) = expected_token else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !token.is_some_and(|token| constant_time_eq(token.as_bytes(), expected_token.as_bytes())) {
+        warn!("Rejected rpc request with a missing or invalid token");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(())
+}
+
+/// The connected websockets and the round-trip time of each one's last answered ping, in
+/// the Prometheus text format. It names clients, so it takes the rpc token as well
+#[instrument(skip_all)]
+async fn metrics_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(status) = authorize_rpc(state.rpc_token.as_deref(), &headers) {
+        return status.into_response();
+    }
+
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.presence.metrics(),
+    )
+        .into_response()
+}
+
+/// 16 random bytes as hex, the same kind of id the frontend makes up for itself
+fn random_client_id() -> String {
+    let mut buf = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut buf);
+
+    buf.iter().fold(String::with_capacity(32), |mut id, byte| {
+        _ = write!(id, "{byte:02x}");
+        id
+    })
+}
+
+/// 16 random bytes encoded as base64
+fn random_id() -> String {
+    let mut buf = [0u8; 16];
+
+    // TODO: Move the random number generator and base64 engine to a shared location for
+    // each request
+    let mut rng = rand::thread_rng();
+    rng.fill_bytes(&mut buf);
+    let engine = GeneralPurpose::new(&STANDARD, GeneralPurposeConfig::default());
+
+    engine.encode(buf)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Serves the frontend's static files and offers every other request to the roc backend's
+/// routes. Files are looked up first, so assets never wait for the model lock
+#[instrument(skip_all, fields(method = %request.method(), uri = %request.uri()))]
+async fn route_handler(State(state): State<AppState>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let static_file = serve_static(&state.static_files, &parts).await;
+    if static_file.status() != StatusCode::NOT_FOUND
+        && static_file.status() != StatusCode::METHOD_NOT_ALLOWED
+    {
+        return static_file;
+    }
+
+    let body = match axum::body::to_bytes(body, MAX_ROUTE_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(err) => {
+            warn!(?err, "Could not read request body");
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+    };
+
+    let server_request = ServerRequest {
+        method: RocStr::from(parts.method.as_str()),
+        path: RocStr::from(parts.uri.path()),
+        query: form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes())
+            .map(|(name, value)| HttpHeader {
+                name: RocStr::from(name.as_ref()),
+                value: RocStr::from(value.as_ref()),
+            })
+            .collect(),
+        headers: parts
+            .headers
+            .iter()
+            .map(|(name, value)| HttpHeader {
+                name: RocStr::from(name.as_str()),
+                value: RocStr::from(String::from_utf8_lossy(value.as_bytes()).as_ref()),
+            })
+            .collect(),
+        body: RocList::from_slice(&body),
+    };
+
+    let response = update_model_with(&state.roc_model, |roc_model| {
+        let BackendRouteReturn { response, update } =
+            roc::backend_route_for_host(roc_model, server_request);
+        let response: Result<ServerResponse, ()> = response.into();
+
+        (update, response.ok().map(into_axum_response))
+    });
+
+    match response {
+        Some(response) => response.unwrap_or(static_file),
+        None => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn serve_static(static_files: &ServeDir, parts: &axum::http::request::Parts) -> Response {
+    let request = Request::from_parts(parts.clone(), Body::empty());
+    match static_files.clone().oneshot(request).await {
+        Ok(response) => response.into_response(),
+        Err(err) => match err {},
+    }
+}
+
+fn into_axum_response(response: ServerResponse) -> Response {
+    let status = StatusCode::from_u16(response.status).unwrap_or_else(|_| {
+        error!(
+            status = response.status,
+            "Route responded with an invalid status"
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+    });
+
+    let mut headers = HeaderMap::new();
+    for header in response.headers.iter() {
+        match (
+            HeaderName::from_bytes(header.name.as_bytes()),
+            HeaderValue::from_str(header.value.as_str()),
+        ) {
+            (Ok(name), Ok(value)) => {
+                headers.append(name, value);
+            }
+            _ => warn!(?header, "Skipping invalid response header"),
+        }
+    }
 
-    ws.on_upgrade(move |socket| handle_websocket_connection(state, socket, client_id, session_id))
+    (status, headers, response.body.as_slice().to_vec()).into_response()
 }
 
-#[instrument(skip(roc_model, ws))]
+#[instrument(skip(roc_model, ws), fields(client_id = tracing::field::Empty))]
 async fn handle_websocket_connection(
-    AppState { clients, roc_model }: AppState,
+    AppState {
+        clients,
+        roc_model,
+        presence,
+        client_ids,
+        delivered,
+        heartbeat,
+        ..
+    }: AppState,
     ws: WebSocket,
-    client_id: String,
-    session_id: String,
+    requested_client_id: Option<String>,
+    mut connection: Connection,
 ) {
-    let (sink, mut stream) = ws.split();
-    {
-        let clients = Arc::clone(&clients);
+    let (mut sink, mut stream) = ws.split();
+    let generation = SOCKET_GENERATION.fetch_add(1, Ordering::Relaxed);
+    let client_id = {
         let mut clients = clients.lock().await;
-        clients.insert(client_id.clone(), sink);
-    }
+        // A reconnecting frontend keeps its client id, unless it belongs to another session
+        let client_id = client_ids.claim(requested_client_id, &connection.session_id);
+        // The frontend asks for this id from now on
+        let assigned = Message::Text(format!(r#"{{"client_id":"{client_id}"}}"#));
+        if sink.send(assigned).await.is_err() {
+            warn!(?client_id, "Could not tell the client its id");
+        }
+        let socket = Socket {
+            session_id: connection.session_id.clone(),
+            generation,
+            sink,
+        };
+        // The old socket of a client that reconnected before its loss was noticed
+        if let Some(mut replaced) = clients.insert(client_id.clone(), socket) {
+            info!(?client_id, "Replacing the socket of a reconnected client");
+            let frame = CloseFrame {
+                code: REPLACED_CLOSE_CODE,
+                reason: REPLACED_CLOSE_REASON.into(),
+            };
+            _ = replaced.sink.send(Message::Close(Some(frame))).await;
+            _ = replaced.sink.close().await;
+        }
+
+        client_id
+    };
+    tracing::Span::current().record("client_id", client_id.as_str());
+    delivered.prune();
+    presence.connect(&roc_model, &client_id, connection.clone());
+
+    let mut pings = tokio::time::interval_at(
+        tokio::time::Instant::now() + heartbeat.interval,
+        heartbeat.interval,
+    );
+    pings.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut pending_pings = Pings::default();
 
     // Recieve messages
     loop {
-        match dbg!(stream.next().await) {
-            Some(Ok(Message::Text(msg))) => {
-                let session_id = session_id.clone();
-                let client_id = client_id.clone();
+        let message = tokio::select! {
+            message = stream.next() => message,
+            _ = pings.tick() => {
+                let (sequence, missed) =
+                    pending_pings.tick(tokio::time::Instant::now(), &heartbeat);
+                if missed >= heartbeat.max_missed {
+                    warn!(missed, "Client stopped answering pings");
+                    break;
+                }
+
+                match clients.lock().await.get_mut(&client_id) {
+                    Some(socket) if socket.generation == generation => {
+                        let ping = Message::Ping(sequence.to_be_bytes().to_vec());
+                        if socket.sink.send(ping).await.is_err() {
+                            warn!("Could not ping websocket");
+                            break;
+                        }
+                    }
+                    _ => {
+                        debug!("Socket was replaced or closed by the backend");
+                        break;
+                    }
+                }
+                continue;
+            }
+        };
+
+        match message {
+            Some(Ok(Message::Text(frame))) => {
+                let (message_id, msg) = split_message_id(&frame);
+                if let Some(message_id) = message_id {
+                    let (new, ack) =
+                        delivered.record(&connection.session_id, &client_id, message_id);
+                    if let Some(socket) = clients.lock().await.get_mut(&client_id) {
+                        let ack = Message::Text(format!(r#"{{"ack":{ack}}}"#));
+                        if socket.sink.send(ack).await.is_err() {
+                            warn!("Could not acknowledge message");
+                        }
+                    }
+                    if !new {
+                        debug!(message_id, "Skipped a message that was already delivered");
+                        continue;
+                    }
+                }
+
+                let client = connection.to_roc(&client_id);
                 let roc_model = Arc::clone(&roc_model);
+                let msg = msg.into_owned();
                 tokio::spawn(async move {
-                    handle_ws_message(roc_model, &client_id, &session_id, &msg).await;
+                    handle_ws_message(roc_model, client, &msg).await;
                 });
             }
+            Some(Ok(Message::Pong(payload))) => {
+                let Some(rtt) = pending_pings.pong(&payload, tokio::time::Instant::now()) else {
+                    continue;
+                };
 
-            // None => {
-            //     let mut clients = clients.lock().await;
-            //     clients.remove(&client_id);
-            //     break;
-            // }
+                let rtt_ms = rtt.as_millis() as u64;
+                debug!(rtt_ms, "Heartbeat");
+                presence.set_metadata(&client_id, "rtt_ms", rtt_ms.to_string());
+                connection
+                    .metadata
+                    .insert("rtt_ms".to_owned(), rtt_ms.to_string());
+            }
+            // Pings from the client are answered by axum
+            Some(Ok(Message::Ping(_))) => {}
+            Some(Ok(Message::Close(_))) | None => break,
+            Some(Err(err)) => {
+                warn!(?err, "Websocket error");
+                break;
+            }
             e => error!(?e, "Unhandled message"),
         }
     }
+
+    // Everything about the client now belongs to the socket that replaced this one
+    let mut clients = clients.lock().await;
+    if clients
+        .get(&client_id)
+        .is_some_and(|socket| socket.generation != generation)
+    {
+        info!("Replaced socket closed");
+        return;
+    }
+
+    info!("Client disconnected");
+    clients.remove(&client_id);
+    drop(clients);
+    client_ids.release(&client_id);
+    topics().remove_client(&client_id);
+    presence.disconnect(&roc_model, &client_id);
 }
 
-#[instrument(skip(model))]
-async fn handle_ws_message(
-    model: Arc<RwLock<Model>>,
-    client_id: &str,
-    session_id: &str,
-    msg: &str,
-) {
+#[instrument(
+    skip(model, client),
+    fields(client_id = client.client_id.as_str(), session_id = client.session_id.as_str())
+)]
+async fn handle_ws_message(model: Arc<RwLock<Model>>, client: Client, msg: &str) {
     debug!("Received message");
-    let roc_model = {
-        let read_lock = model.read().expect("Could not acquire lock");
-        read_lock.clone()
+    update_model(&model, |roc_model| {
+        roc::backend_update_for_host(roc_model, client, RocStr::from(msg))
+    });
+}
+
+/// Runs a roc update against the current model, keeping the write lock for the duration of the
+/// update so that concurrent updates are not lost, then carries out the requested effects
+fn update_model(model: &Arc<RwLock<Model>>, update: impl FnOnce(Model) -> BackendUpdateReturn) {
+    update_model_with(model, |roc_model| (update(roc_model), ()));
+}
+
+/// Like [`update_model`] for roc entrypoints that return something besides the update. Gives
+/// nothing back when the roc backend crashed, in which case the model stays as it was
+fn update_model_with<T>(
+    model: &Arc<RwLock<Model>>,
+    update: impl FnOnce(Model) -> (BackendUpdateReturn, T),
+) -> Option<T> {
+    let (effects, extra) = {
+        // The model is only replaced once an update succeeded, so it is still whole when a
+        // panic poisoned the lock
+        let mut write_lock = model.write().unwrap_or_else(PoisonError::into_inner);
+        let current = write_lock.clone();
+        let Ok((
+            BackendUpdateReturn {
+                model: updated_model,
+                effects,
+            },
+            extra,
+        )) = panic::catch_unwind(AssertUnwindSafe(|| update(current)))
+        else {
+            error!("The roc backend crashed, the update was dropped");
+            return None;
+        };
+        *write_lock = unsafe { Model::init(updated_model) };
+
+        (effects, extra)
     };
 
-    let BackendUpdateReturn {
-        model: updated_model,
-        to_frontend,
-    } = roc::backend_update_for_host(
-        roc_model.clone(),
-        RocStr::from(client_id),
-        RocStr::from(session_id),
-        RocStr::from(msg),
-    );
+    run_effects(model, effects);
 
-    match model.write() {
-        Ok(mut write_lock) => {
-            *write_lock = unsafe { Model::init(updated_model) };
+    Some(extra)
+}
+
+fn run_effects(model: &Arc<RwLock<Model>>, effects: Effects) {
+    let mut to_frontend = effects
+        .to_frontend
+        .iter()
+        .map(|message| {
+            MessageInfo::new(
+                message.client_id.as_str().to_owned(),
+                message.reply_to,
+                message.message.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let http_requests = effects
+        .http_requests
+        .iter()
+        .map(http::Request::from)
+        .collect::<Vec<_>>();
+    let db_requests = effects
+        .db_requests
+        .iter()
+        .map(db::Request::from)
+        .collect::<Vec<_>>();
+    for job in effects.jobs.iter() {
+        if let Err(err) = jobs::enqueue(jobs::NewJob::from(job)) {
+            error!(?err, name = job.name.as_str(), "Could not queue job");
+        }
+    }
+    {
+        let mut topics = topics();
+        for change in effects.topics.iter() {
+            if change.subscribe {
+                topics.subscribe(change.client_id.as_str(), change.topic.as_str());
+            } else {
+                topics.unsubscribe(change.client_id.as_str(), change.topic.as_str());
+            }
+        }
+        // Each message is encoded once by the roc backend and only copied per subscriber
+        for published in effects.published.iter() {
+            let Some(subscribers) = topics.subscribers.get(published.topic.as_str()) else {
+                continue;
+            };
+            debug!(
+                topic = published.topic.as_str(),
+                subscribers = subscribers.len(),
+                "Publishing"
+            );
+            to_frontend.extend(subscribers.iter().map(|client_id| {
+                MessageInfo::new(client_id.clone(), 0, published.message.as_str())
+            }));
         }
+    }
+    warn!(?to_frontend, "To Frontend");
+
+    // Closes go out after the messages so that a client still gets what was sent to it
+    // before being disconnected
+    let closes = effects
+        .disconnects
+        .iter()
+        .map(|disconnect| Outgoing::Close {
+            target: if disconnect.session {
+                CloseTarget::Session(disconnect.id.as_str().to_owned())
+            } else {
+                CloseTarget::Client(disconnect.id.as_str().to_owned())
+            },
+            code: close_code(disconnect.code),
+            reason: close_reason(disconnect.reason.as_str()),
+        });
+    let outgoing = to_frontend
+        .into_iter()
+        .map(Outgoing::from)
+        .chain(closes)
+        .collect::<Vec<_>>();
+
+    if let Some(tx) = CHANNEL_SENDER.get() {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            for message in outgoing {
+                tx.send(message).await.expect("Could not send message");
+            }
+        });
+    }
+
+    for request in http_requests {
+        let model = Arc::clone(model);
+        tokio::spawn(async move {
+            let request_id = request.id;
+            let response = http::send(request).await;
+            update_model(&model, |roc_model| {
+                roc::backend_http_response_for_host(
+                    roc_model,
+                    request_id,
+                    HttpResponse::from(response),
+                )
+            });
+        });
+    }
+
+    // Transactions from a single update run one after another in the order they were issued
+    if !db_requests.is_empty() {
+        let model = Arc::clone(model);
+        tokio::spawn(async move {
+            for request in db_requests {
+                let request_id = request.id;
+                let response = db::run(request).await;
+                update_model(&model, |roc_model| {
+                    roc::backend_db_response_for_host(
+                        roc_model,
+                        request_id,
+                        DbResponse::from(response),
+                    )
+                });
+            }
+        });
+    }
+}
+
+/// Starts the workers that run queued jobs, each one job at a time
+fn spawn_job_workers(model: &Arc<RwLock<Model>>) {
+    let workers = job_workers(env::var("GALENA_JOB_WORKERS").ok().as_deref());
+
+    for _ in 0..workers {
+        let model = Arc::clone(model);
+        tokio::spawn(async move {
+            loop {
+                match jobs::next().await {
+                    Ok(job) => run_job(&model, job).await,
+                    Err(err) => {
+                        error!(?err, "Could not take job from queue");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// The number of workers `GALENA_JOB_WORKERS` asks for. Queued jobs would never run without
+/// a worker, so anything but a positive number falls back to the default
+fn job_workers(value: Option<&str>) -> usize {
+    let Some(value) = value else {
+        return DEFAULT_JOB_WORKERS;
+    };
+    match value.trim().parse() {
+        Ok(workers) if workers > 0 => workers,
         _ => {
-            drop(updated_model);
+            error!(
+                value,
+                default = DEFAULT_JOB_WORKERS,
+                "GALENA_JOB_WORKERS must be a positive number, using the default"
+            );
+            DEFAULT_JOB_WORKERS
         }
     }
+}
 
-    let to_frontend: Result<_, _> = to_frontend.into();
-    let to_frontend = to_frontend.map(|to_frontend: ToFrontend| {
-        (
-            to_frontend.client_id.as_str().to_owned(),
-            to_frontend.message.as_str().to_owned(),
+#[instrument(skip_all, fields(id = job.id, name = job.name, attempt = job.attempts))]
+async fn run_job(model: &Arc<RwLock<Model>>, job: jobs::Job) {
+    let outcome = {
+        let name = job.name.clone();
+        let payload = job.payload.clone();
+        tokio::task::spawn_blocking(move || {
+            let outcome = roc::backend_run_job_for_host(
+                RocStr::from(name.as_str()),
+                RocList::from_slice(&payload),
+            );
+            (
+                outcome.failure,
+                outcome.error.as_str().to_owned(),
+                outcome.output.as_slice().to_vec(),
+            )
+        })
+        .await
+    };
+    let (failure, error, output) = match outcome {
+        Ok(outcome) => outcome,
+        Err(err) => (1, err.to_string(), Vec::new()),
+    };
+
+    match failure {
+        1 if job.attempts < job.max_attempts => {
+            warn!(error, backoff = ?job.backoff(), "Job failed, retrying");
+            if let Err(err) = jobs::retry(&job) {
+                error!(?err, "Could not requeue job");
+            }
+            return;
+        }
+        3 => {
+            error!("No job with this name is registered, dropping it");
+            if let Err(err) = jobs::finish(&job) {
+                error!(?err, "Could not remove job");
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    // The job is only removed once the backend has seen the outcome so a crash in between
+    // runs it again rather than losing it
+    update_model(model, |roc_model| {
+        roc::backend_job_done_for_host(
+            roc_model,
+            JobCompletion {
+                error: RocStr::from(error.as_str()),
+                name: RocStr::from(job.name.as_str()),
+                output: RocList::from_slice(&output),
+                payload: RocList::from_slice(&job.payload),
+                attempts: job.attempts,
+                failure,
+            },
         )
     });
-    warn!(?to_frontend, "To Frontend");
+    if let Err(err) = jobs::finish(&job) {
+        error!(?err, "Could not remove finished job");
+    }
+}
 
-    if let Ok((client_id, msg_bytes)) = to_frontend {
-        if let Some(tx) = CHANNEL_SENDER.get() {
-            tx.send(MessageInfo {
-                client_id,
-                msg_bytes,
-            })
-            .await
-            .expect("Could not send message");
+/// Where the backend keeps its durable state such as the app's database
+fn startup() -> BackendStartup {
+    BackendStartup {
+        address: ADDRESS.into(),
+        build_version: env::var("GALENA_BUILD_VERSION")
+            .unwrap_or_else(|_| "dev".to_owned())
+            .as_str()
+            .into(),
+        data_dir: data_dir().to_string_lossy().as_ref().into(),
+        now_ms: now_ms(),
+        restoring: false,
+    }
+}
+
+/// Close codes an endpoint may send, anything else becomes the first code reserved for apps
+fn close_code(code: u16) -> u16 {
+    match code {
+        1000 | 3000..=4999 => code,
+        _ => 4000,
+    }
+}
+
+/// Cuts a close reason down to the 123 bytes that fit in a close frame
+fn close_reason(reason: &str) -> String {
+    let mut end = reason.len().min(123);
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    reason[..end].to_owned()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn data_dir() -> PathBuf {
+    env::var("GALENA_DATA_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("data"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxies(addresses: &[&str]) -> TrustedProxies {
+        TrustedProxies {
+            addresses: addresses
+                .iter()
+                .map(|address| address.parse().unwrap())
+                .collect(),
+            identity_header: Some(HeaderName::from_static("x-user")),
+        }
+    }
+
+    fn forwarded_for(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(X_FORWARDED_FOR, HeaderValue::from_str(value).unwrap());
         }
+        headers
+    }
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_peers() {
+        let proxies = proxies(&["10.0.0.1"]);
+        let headers = forwarded_for(&["6.6.6.6"]);
+
+        assert_eq!(
+            proxies.client_address(ip("1.2.3.4"), &headers),
+            ip("1.2.3.4")
+        );
+        assert_eq!(
+            TrustedProxies::default().client_address(ip("10.0.0.1"), &headers),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn the_client_is_the_address_a_trusted_proxy_saw() {
+        let proxies = proxies(&["10.0.0.1"]);
+
+        assert_eq!(
+            proxies.client_address(ip("10.0.0.1"), &forwarded_for(&["1.2.3.4"])),
+            ip("1.2.3.4")
+        );
+        assert_eq!(
+            proxies.client_address(ip("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn addresses_spoofed_by_the_client_are_skipped() {
+        let proxies = proxies(&["10.0.0.1", "10.0.0.2"]);
+        let peer = ip("10.0.0.1");
+
+        // The client sent its own header, which the proxy appended the real address to
+        let spoofed = forwarded_for(&["6.6.6.6, 1.2.3.4"]);
+        assert_eq!(proxies.client_address(peer, &spoofed), ip("1.2.3.4"));
+
+        // Pretending to be a trusted proxy does not get the client any further
+        let spoofed = forwarded_for(&["6.6.6.6, 10.0.0.2, 1.2.3.4"]);
+        assert_eq!(proxies.client_address(peer, &spoofed), ip("1.2.3.4"));
+
+        // The same with the client's header sent on a line of its own
+        let spoofed = forwarded_for(&["6.6.6.6", "1.2.3.4"]);
+        assert_eq!(proxies.client_address(peer, &spoofed), ip("1.2.3.4"));
+    }
+
+    #[test]
+    fn chains_of_trusted_proxies_are_followed() {
+        let proxies = proxies(&["10.0.0.1", "10.0.0.2"]);
+        let headers = forwarded_for(&["6.6.6.6, 1.2.3.4, 10.0.0.2"]);
+
+        assert_eq!(
+            proxies.client_address(ip("10.0.0.1"), &headers),
+            ip("1.2.3.4")
+        );
+    }
+
+    #[test]
+    fn invalid_hops_end_the_walk() {
+        let proxies = proxies(&["10.0.0.1", "10.0.0.2"]);
+
+        assert_eq!(
+            proxies.client_address(ip("10.0.0.1"), &forwarded_for(&["1.2.3.4, unknown"])),
+            ip("10.0.0.1")
+        );
+        assert_eq!(
+            proxies.client_address(ip("10.0.0.1"), &forwarded_for(&["unknown, 10.0.0.2"])),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn identities_are_only_believed_from_trusted_proxies() {
+        let proxies = proxies(&["10.0.0.1"]);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-user", HeaderValue::from_static("alice"));
+
+        assert_eq!(
+            proxies.identity(ip("10.0.0.1"), &headers).as_deref(),
+            Some("alice")
+        );
+        assert_eq!(proxies.identity(ip("1.2.3.4"), &headers), None);
+    }
+
+    /// The clients subscribed to a topic, sorted
+    fn subscribers<'a>(topics: &'a Topics, topic: &str) -> Vec<&'a str> {
+        let mut subscribers: Vec<_> = topics
+            .subscribers
+            .get(topic)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        subscribers.sort_unstable();
+        subscribers
+    }
+
+    #[test]
+    fn topics_are_indexed_both_ways() {
+        let mut topics = Topics::default();
+        topics.subscribe("alice", "chat");
+        topics.subscribe("bob", "chat");
+        topics.subscribe("alice", "news");
+        topics.subscribe("alice", "chat");
+
+        assert_eq!(subscribers(&topics, "chat"), ["alice", "bob"]);
+        assert_eq!(subscribers(&topics, "news"), ["alice"]);
+        assert_eq!(topics.subscriptions["alice"].len(), 2);
+        assert_eq!(topics.subscriptions["bob"].len(), 1);
+    }
+
+    #[test]
+    fn unsubscribing_drops_empty_entries() {
+        let mut topics = Topics::default();
+        topics.subscribe("alice", "chat");
+        topics.subscribe("bob", "chat");
+        topics.unsubscribe("alice", "chat");
+
+        assert_eq!(subscribers(&topics, "chat"), ["bob"]);
+        assert!(!topics.subscriptions.contains_key("alice"));
+
+        topics.unsubscribe("bob", "chat");
+        assert!(topics.subscribers.is_empty());
+        assert!(topics.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn disconnected_clients_are_pruned_from_their_topics() {
+        let mut topics = Topics::default();
+        topics.subscribe("alice", "chat");
+        topics.subscribe("alice", "news");
+        topics.subscribe("bob", "chat");
+        topics.remove_client("alice");
+
+        assert_eq!(subscribers(&topics, "chat"), ["bob"]);
+        assert!(!topics.subscribers.contains_key("news"));
+        assert!(!topics.subscriptions.contains_key("alice"));
+
+        // Clients without subscriptions disconnect too
+        topics.remove_client("carol");
+        assert_eq!(subscribers(&topics, "chat"), ["bob"]);
+    }
+
+    #[test]
+    fn message_ids_are_split_off() {
+        let (message_id, msg) = split_message_id(r#"{"message_id":7,"request_id":0,"msg":3}"#);
+
+        assert_eq!(message_id, Some(7));
+        assert_eq!(msg, r#"{"request_id":0,"msg":3}"#);
+    }
+
+    #[test]
+    fn frames_without_a_message_id_are_left_alone() {
+        for frame in [
+            r#"{"request_id":0,"msg":3}"#,
+            r#"{"message_id":"7","request_id":0,"msg":3}"#,
+            r#"{"message_id":-1,"request_id":0,"msg":3}"#,
+            r#"{"message_id":7}"#,
+        ] {
+            let (message_id, msg) = split_message_id(frame);
+
+            assert_eq!(message_id, None, "{frame}");
+            assert!(matches!(msg, Cow::Borrowed(msg) if msg == frame), "{frame}");
+        }
+    }
+
+    #[test]
+    fn messages_are_delivered_once() {
+        let delivered = Delivered::default();
+
+        assert_eq!(delivered.record("session", "client", 1), (true, 1));
+        assert_eq!(delivered.record("session", "client", 2), (true, 2));
+        assert_eq!(delivered.record("session", "client", 1), (false, 2));
+        assert_eq!(delivered.record("session", "client", 2), (false, 2));
+        assert_eq!(delivered.record("session", "other client", 1), (true, 1));
+    }
+
+    #[test]
+    fn delivered_messages_are_kept_apart_by_session() {
+        let delivered = Delivered::default();
+        delivered.record("session", "client", 5);
+
+        assert_eq!(delivered.record("other session", "client", 1), (true, 1));
+    }
+
+    #[test]
+    fn job_workers_are_read_from_the_variable() {
+        assert_eq!(job_workers(None), DEFAULT_JOB_WORKERS);
+        assert_eq!(job_workers(Some("8")), 8);
+        assert_eq!(job_workers(Some(" 2 ")), 2);
+    }
+
+    #[test]
+    fn there_is_always_a_job_worker() {
+        for value in ["0", "-1", "", "four"] {
+            assert_eq!(job_workers(Some(value)), DEFAULT_JOB_WORKERS, "{value}");
+        }
+    }
+
+    #[test]
+    fn close_codes_an_endpoint_may_send_are_kept() {
+        for code in [1000, 3000, 4001, 4999] {
+            assert_eq!(close_code(code), code);
+        }
+    }
+
+    #[test]
+    fn replaced_sockets_are_closed_for_good() {
+        // Frontends reconnect after any other code
+        assert!(matches!(REPLACED_CLOSE_CODE, 1000 | 3000..=4999));
+        assert_eq!(close_code(REPLACED_CLOSE_CODE), REPLACED_CLOSE_CODE);
+        assert_eq!(close_reason(REPLACED_CLOSE_REASON), REPLACED_CLOSE_REASON);
+    }
+
+    #[test]
+    fn other_close_codes_are_replaced() {
+        // Reserved for the protocol, never sent or not meant for apps
+        for code in [0, 999, 1001, 1005, 1006, 1011, 2999, 5000, u16::MAX] {
+            assert_eq!(close_code(code), 4000, "{code}");
+        }
+    }
+
+    #[test]
+    fn short_close_reasons_are_kept() {
+        assert_eq!(close_reason(""), "");
+        assert_eq!(close_reason("You were logged out"), "You were logged out");
+        assert_eq!(close_reason(&"a".repeat(123)), "a".repeat(123));
+    }
+
+    #[test]
+    fn long_close_reasons_are_cut_to_fit_a_frame() {
+        assert_eq!(close_reason(&"a".repeat(200)), "a".repeat(123));
+
+        // 61 two byte characters end at byte 122, the next one would end past 123
+        let reason = close_reason(&"é".repeat(100));
+        assert_eq!(reason, "é".repeat(61));
+        assert_eq!(reason.len(), 122);
+    }
+
+    fn heartbeat(max_missed: u32) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(15),
+            max_missed,
+        }
+    }
+
+    #[test]
+    fn answered_pings_are_not_missed() {
+        let heartbeat = heartbeat(3);
+        let start = tokio::time::Instant::now();
+        let mut pings = Pings::default();
+
+        for round in 0..5 {
+            let sent_at = start + heartbeat.interval * round;
+            let (sequence, missed) = pings.tick(sent_at, &heartbeat);
+            assert_eq!(missed, 0);
+
+            let rtt = pings.pong(&sequence.to_be_bytes(), sent_at + Duration::from_millis(40));
+            assert_eq!(rtt, Some(Duration::from_millis(40)));
+        }
+    }
+
+    #[test]
+    fn a_ping_is_missed_when_it_is_unanswered_at_the_next_tick() {
+        let heartbeat = heartbeat(3);
+        let start = tokio::time::Instant::now();
+        let mut pings = Pings::default();
+
+        assert_eq!(pings.tick(start, &heartbeat).1, 0);
+        assert_eq!(pings.tick(start + heartbeat.interval, &heartbeat).1, 1);
+        assert_eq!(pings.tick(start + heartbeat.interval * 2, &heartbeat).1, 2);
+        assert_eq!(pings.tick(start + heartbeat.interval * 3, &heartbeat).1, 3);
+    }
+
+    #[test]
+    fn late_pongs_still_count() {
+        let heartbeat = heartbeat(3);
+        let start = tokio::time::Instant::now();
+        let mut pings = Pings::default();
+        let (first, _) = pings.tick(start, &heartbeat);
+        let (second, missed) = pings.tick(start + heartbeat.interval, &heartbeat);
+        assert_eq!(missed, 1);
+
+        // The pong of the first ping arrives after the second one was sent
+        let late = start + heartbeat.interval + Duration::from_secs(1);
+        assert_eq!(
+            pings.pong(&first.to_be_bytes(), late),
+            Some(heartbeat.interval + Duration::from_secs(1))
+        );
+        assert_eq!(pings.missed, 0);
+
+        // The second ping is answered in time, so the next tick misses nothing
+        pings.pong(&second.to_be_bytes(), late);
+        assert_eq!(pings.tick(start + heartbeat.interval * 2, &heartbeat).1, 0);
+    }
+
+    #[test]
+    fn a_pong_answers_the_pings_before_it() {
+        let heartbeat = heartbeat(3);
+        let start = tokio::time::Instant::now();
+        let mut pings = Pings::default();
+        let (first, _) = pings.tick(start, &heartbeat);
+        let (second, _) = pings.tick(start + heartbeat.interval, &heartbeat);
+
+        assert!(pings
+            .pong(&second.to_be_bytes(), start + heartbeat.interval)
+            .is_some());
+        assert_eq!(
+            pings.pong(&first.to_be_bytes(), start + heartbeat.interval),
+            None
+        );
+        assert_eq!(pings.tick(start + heartbeat.interval * 2, &heartbeat).1, 0);
+    }
+
+    #[test]
+    fn unknown_pongs_are_ignored() {
+        let heartbeat = heartbeat(3);
+        let start = tokio::time::Instant::now();
+        let mut pings = Pings::default();
+        let (sequence, _) = pings.tick(start, &heartbeat);
+
+        assert_eq!(pings.pong(&(sequence + 1).to_be_bytes(), start), None);
+        assert_eq!(pings.pong(b"hello", start), None);
+        assert_eq!(pings.pong(&[], start), None);
+        assert_eq!(pings.tick(start + heartbeat.interval, &heartbeat).1, 1);
+    }
+
+    #[test]
+    fn metrics_list_the_rtt_of_each_client() {
+        let presence = Presence::default();
+        let connection = Connection::capture(
+            "session".to_owned(),
+            SocketAddr::from(([127, 0, 0, 1], 4000)),
+            &HeaderMap::new(),
+            &TrustedProxies::default(),
+        );
+        for client_id in ["b", "a", "c"] {
+            presence
+                .connections
+                .lock()
+                .unwrap()
+                .insert(client_id.to_owned(), connection.clone());
+        }
+        presence.set_metadata("a", "rtt_ms", "12".to_owned());
+        presence.set_metadata("b", "rtt_ms", "7".to_owned());
+
+        let metrics = presence.metrics();
+        assert!(
+            metrics.contains("\ngalena_connected_clients 3\n"),
+            "{metrics}"
+        );
+        assert!(
+            metrics.ends_with(
+                "galena_client_rtt_ms{client_id=\"a\"} 12\n\
+                galena_client_rtt_ms{client_id=\"b\"} 7\n"
+            ),
+            "{metrics}"
+        );
+    }
+
+    #[tokio::test]
+    async fn static_files_are_found_without_the_backend() {
+        let dist = std::env::temp_dir().join(format!("galena-dist-{}", std::process::id()));
+        std::fs::create_dir_all(&dist).unwrap();
+        std::fs::write(dist.join("app.js"), "console.log(1)").unwrap();
+        let static_files = ServeDir::new(&dist);
+        let parts = |method: &str, path: &str| {
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .body(())
+                .unwrap()
+                .into_parts()
+                .0
+        };
+
+        let found = serve_static(&static_files, &parts("GET", "/app.js")).await;
+        let missing = serve_static(&static_files, &parts("GET", "/api/counter")).await;
+        let posted = serve_static(&static_files, &parts("POST", "/app.js")).await;
+        std::fs::remove_dir_all(&dist).unwrap();
+
+        assert_eq!(found.status(), StatusCode::OK);
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+        assert_eq!(posted.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    fn unchanged(roc_model: Model) -> BackendUpdateReturn {
+        BackendUpdateReturn {
+            effects: Effects::default(),
+            model: roc_model.inner,
+        }
+    }
+
+    #[test]
+    fn a_crashed_update_leaves_the_model_usable() {
+        let model = Arc::new(RwLock::new(unsafe {
+            Model::init(roc_std::RocBox::new(()))
+        }));
+
+        let crashed: Option<()> = update_model_with(&model, |_| panic!("Roc paniced"));
+        assert!(crashed.is_none());
+        assert!(!model.is_poisoned());
+
+        let answered = update_model_with(&model, |roc_model| (unchanged(roc_model), 42));
+        assert_eq!(answered, Some(42));
+    }
+
+    #[test]
+    fn a_poisoned_model_lock_is_recovered() {
+        let model = Arc::new(RwLock::new(unsafe {
+            Model::init(roc_std::RocBox::new(()))
+        }));
+        let poisoner = Arc::clone(&model);
+        _ = std::thread::spawn(move || {
+            let _guard = poisoner.write().unwrap();
+            panic!("poisoning the lock");
+        })
+        .join();
+        assert!(model.is_poisoned());
+
+        let answered = update_model_with(&model, |roc_model| (unchanged(roc_model), 42));
+        assert_eq!(answered, Some(42));
+    }
+
+    #[test]
+    fn client_ids_are_hex() {
+        let client_id = random_client_id();
+
+        assert_eq!(client_id.len(), 32);
+        assert!(client_id.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert!(valid_client_id(&client_id));
+    }
+
+    #[test]
+    fn a_session_keeps_its_client_id() {
+        let client_ids = ClientIds::default();
+        let client_id = client_ids.claim(None, "session");
+        client_ids.release(&client_id);
+
+        assert_eq!(
+            client_ids.claim(Some(client_id.clone()), "session"),
+            client_id
+        );
+    }
+
+    #[test]
+    fn a_free_client_id_is_kept() {
+        let client_ids = ClientIds::default();
+
+        assert_eq!(
+            client_ids.claim(Some("picked-by-the-frontend".to_owned()), "session"),
+            "picked-by-the-frontend"
+        );
+    }
+
+    #[test]
+    fn other_sessions_get_a_new_client_id() {
+        let client_ids = ClientIds::default();
+        let client_id = client_ids.claim(None, "session");
+        let other = client_ids.claim(Some(client_id.clone()), "other session");
+        client_ids.release(&client_id);
+        let after_release = client_ids.claim(Some(client_id.clone()), "other session");
+
+        assert_ne!(other, client_id);
+        assert!(valid_client_id(&other));
+        assert_ne!(after_release, client_id);
     }
 }
@@ -0,0 +1,227 @@
+//! Event handlers are not attached to the elements they are declared on. The root container
+//! gets one listener per event type, which looks up the handler of the element the event
+//! is for, so rerenders only ever swap entries in a table
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use roc_std::RocList;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::js_sys::{Reflect, JSON};
+use web_sys::{Element, Event, Node};
+
+use crate::roc::{EventProperty, HostEventValue, RocFunction};
+
+/// The property of an element that holds the id of its handlers
+const HANDLERS_PROPERTY: &str = "__galenaHandlers";
+
+// The kinds of `Internal.Event.EventProperty`
+const KIND_MISSING: u8 = 0;
+const KIND_BOOL: u8 = 1;
+const KIND_NUMBER: u8 = 2;
+const KIND_STRING: u8 = 3;
+const KIND_JSON: u8 = 4;
+
+type Listener = Closure<dyn FnMut(Event)>;
+
+thread_local! {
+    static ROOT: RefCell<Option<Element>> = const { RefCell::new(None) };
+    // The event types the root listens to, with the listener to keep it alive
+    static LISTENERS: RefCell<HashMap<String, Listener>> = RefCell::new(HashMap::new());
+    static HANDLERS: RefCell<HashMap<u32, Vec<Handler>>> = RefCell::new(HashMap::new());
+    static NEXT_ID: Cell<u32> = const { Cell::new(0) };
+}
+
+/// An element's handler for one event type
+#[derive(Clone)]
+pub struct Handler {
+    pub event_type: String,
+    /// Read from the event and handed to the callback along with it
+    pub properties: Vec<Property>,
+    pub callback: RocFunction,
+}
+
+#[derive(Clone)]
+pub struct Property {
+    path: Vec<String>,
+    kind: u8,
+    optional: bool,
+}
+
+impl Property {
+    pub fn from_roc(property: &EventProperty) -> Self {
+        Property {
+            path: property
+                .path
+                .iter()
+                .map(|key| key.as_str().to_owned())
+                .collect(),
+            kind: property.kind,
+            optional: property.optional,
+        }
+    }
+
+    /// The value at the property's path, unless it is missing or of another kind
+    fn read(&self, event: &Event) -> Option<HostEventValue> {
+        let mut value = JsValue::from(event);
+        for key in &self.path {
+            if !value.is_object() {
+                return None;
+            }
+            value = Reflect::get(&value, &key.into()).ok()?;
+        }
+
+        let (number, text) = match self.kind {
+            KIND_BOOL => (f64::from(u8::from(value.as_bool()?)), String::new()),
+            KIND_NUMBER => (value.as_f64()?, String::new()),
+            KIND_STRING => (0.0, value.as_string()?),
+            KIND_JSON if !value.is_undefined() => (0.0, JSON::stringify(&value).ok()?.into()),
+            _ => return None,
+        };
+
+        Some(HostEventValue {
+            number,
+            text: text.as_str().into(),
+            kind: self.kind,
+        })
+    }
+}
+
+/// The handlers of one element, which stay registered for as long as this is alive
+pub struct Handlers {
+    id: u32,
+}
+
+impl Handlers {
+    pub fn register(element: &Element) -> Result<Self, JsValue> {
+        let id = NEXT_ID.replace(NEXT_ID.get().wrapping_add(1));
+        Reflect::set(element, &HANDLERS_PROPERTY.into(), &id.into())?;
+
+        Ok(Handlers { id })
+    }
+
+    pub fn set(&self, events: Vec<Handler>) -> Result<(), JsValue> {
+        for handler in &events {
+            listen(&handler.event_type)?;
+        }
+        HANDLERS.with_borrow_mut(|handlers| handlers.insert(self.id, events));
+
+        Ok(())
+    }
+}
+
+impl Drop for Handlers {
+    fn drop(&mut self) {
+        HANDLERS.with_borrow_mut(|handlers| handlers.remove(&self.id));
+    }
+}
+
+/// Sets the element whose listeners handle the events of everything rendered into it
+pub fn delegate_from(root: Element) {
+    ROOT.set(Some(root));
+}
+
+fn listen(event_type: &str) -> Result<(), JsValue> {
+    if LISTENERS.with_borrow(|listeners| listeners.contains_key(event_type)) {
+        return Ok(());
+    }
+    let root = ROOT
+        .with_borrow(Clone::clone)
+        .ok_or("Events are not delegated to a root yet")?;
+
+    let closure = {
+        let root = root.clone();
+        Closure::wrap(Box::new(move |event: Event| {
+            dispatch(&root, &event);
+        }) as Box<dyn FnMut(Event)>)
+    };
+    // Events that do not bubble, like focus or mouseenter, only reach the root while
+    // capturing, so the listener is registered for both phases
+    root.add_event_listener_with_callback_and_bool(
+        event_type,
+        closure.as_ref().unchecked_ref(),
+        true,
+    )?;
+    root.add_event_listener_with_callback(event_type, closure.as_ref().unchecked_ref())?;
+    LISTENERS.with_borrow_mut(|listeners| listeners.insert(event_type.to_owned(), closure));
+
+    Ok(())
+}
+
+/// Calls the handlers of the target and the elements around it, from the inside out, until
+/// one stops the propagation. Events that do not bubble are only handled by their target
+fn dispatch(root: &Element, event: &Event) {
+    // Bubbling events are handled on the way up, the others on the way down
+    let capturing = event.event_phase() == Event::CAPTURING_PHASE;
+    if event.bubbles() == capturing {
+        return;
+    }
+
+    let event_type = event.type_();
+    let mut node = event
+        .target()
+        .and_then(|target| target.dyn_into::<Node>().ok());
+
+    while let Some(current) = node {
+        if let Some(element) = current.dyn_ref::<Element>() {
+            let found = handler(element, &event_type);
+            if let Some((mut callback, values)) =
+                found.and_then(|handler| read_values(event, handler))
+            {
+                let mut internal_event = crate::convert_web_event_to_internal(event, element);
+                internal_event.values = values;
+                let result = callback.force_thunk(internal_event);
+
+                if result.prevent_default {
+                    event.prevent_default();
+                }
+                if result.stop_propagation {
+                    event.stop_propagation();
+                }
+                crate::update_model_and_rerender(result.message);
+                if result.stop_propagation {
+                    return;
+                }
+            }
+        }
+        if !event.bubbles() || current.is_same_node(Some(root)) {
+            return;
+        }
+        node = current.parent_node();
+    }
+}
+
+/// A copy of the element's handler for the event, so that the table is not borrowed while
+/// the update runs
+fn handler(element: &Element, event_type: &str) -> Option<Handler> {
+    let id = Reflect::get(element, &HANDLERS_PROPERTY.into())
+        .ok()?
+        .as_f64()? as u32;
+
+    HANDLERS.with_borrow(|handlers| {
+        handlers
+            .get(&id)?
+            .iter()
+            .find(|handler| handler.event_type == event_type)
+            .cloned()
+    })
+}
+
+/// The properties the handler asks for, or nothing when the handler ignores the event
+/// because one of them is missing
+fn read_values(event: &Event, handler: Handler) -> Option<(RocFunction, RocList<HostEventValue>)> {
+    let mut values = Vec::with_capacity(handler.properties.len());
+    for property in &handler.properties {
+        match property.read(event) {
+            Some(value) => values.push(value),
+            None if property.optional => values.push(HostEventValue {
+                kind: KIND_MISSING,
+                ..Default::default()
+            }),
+            None => return None,
+        }
+    }
+
+    Some((handler.callback, RocList::from_slice(&values)))
+}
@@ -1,26 +1,26 @@
+mod events;
 mod roc;
+mod vdom;
 mod ws;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::sync::{Arc, LazyLock, Mutex};
 
 use roc::Model;
-use roc::{frontend_decode_to_frontend_msg, UpdateResult};
+use roc::{HostEffect, ToBackend, UpdateResult};
 use roc_std::RocBox;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::js_sys::Array;
 use web_sys::WebSocket;
 use web_sys::{self, console};
-use web_sys::{Document, Element, Event, HtmlInputElement};
+use web_sys::{Element, Event, HtmlElement, HtmlInputElement};
 use wee_alloc::WeeAlloc;
 
-use roc::{
-    discriminant_InternalAttr, discriminant_InternalHtml, InternalAttr, InternalEvent,
-    InternalHtml, InternalHtmlElementFields, R3,
-};
+use roc::{InternalEvent, InternalHtml, R3};
 
-use crate::ws::ReconnectingWebSocket;
+use crate::vdom::{Mounted, VNode};
+use crate::ws::{ConnectionStatus, ReconnectingWebSocket};
 
 #[global_allocator]
 static ALLOC: WeeAlloc<'_> = WeeAlloc::INIT;
@@ -31,9 +31,19 @@ static MODEL: LazyLock<Arc<Mutex<roc::Model>>> = LazyLock::new(|| {
 });
 
 thread_local! {
-    // Thread-local storage for the DomBuilder to keep event handlers alive
-    static BUILDER_STORAGE: std::cell::RefCell<Option<DomBuilder>> = std::cell::RefCell::new(None);
-    static WS: std::cell::RefCell<ReconnectingWebSocket> = std::cell::RefCell::new( ReconnectingWebSocket::new(get_ws_url()) );
+    // The last render, which the next one is compared against
+    static VDOM: RefCell<Option<Mounted>> = const { RefCell::new(None) };
+    // Set when the model changed during a render, e.g. by a blur event fired by removing
+    // the focused element, so that the render is repeated
+    static RERENDER: Cell<bool> = const { Cell::new(false) };
+    // Set while a render is waiting for the next animation frame
+    static RENDER_SCHEDULED: Cell<bool> = const { Cell::new(false) };
+    // Set by `set_sync_rendering`, makes every update render before it returns
+    static SYNC_RENDERING: Cell<bool> = const { Cell::new(false) };
+    // Effects that look up elements, which run once the update that asked for them has
+    // been rendered
+    static DOM_EFFECTS: RefCell<Vec<HostEffect>> = const { RefCell::new(Vec::new()) };
+    static WS: std::cell::RefCell<ReconnectingWebSocket> = std::cell::RefCell::new( ReconnectingWebSocket::new(get_ws_url(), roc::frontend_config_for_host().offline_storage) );
 }
 
 #[wasm_bindgen]
@@ -48,19 +58,53 @@ pub fn run() {
     // Initial render
     render_app();
 
-    // Setup WebSocket
+    // Setup WebSocket. It is only borrowed shared, so that handlers running while it
+    // connects can still send messages
     WS.with(|ws: &RefCell<ReconnectingWebSocket>| {
-        let mut ws = ws.borrow_mut();
-        ws.connect().expect("Failed to connect to websocket");
+        let ws = ws.borrow();
         ws.set_onmessage(|message_event| {
             if let Some(data) = message_event.data().as_string() {
-                let msg = frontend_decode_to_frontend_msg(data.as_bytes().into());
-                update_model_and_rerender(msg);
+                apply_update(|model| roc::frontend_receive_for_host(model, data.as_bytes().into()));
             }
         });
+        if roc::frontend_config_for_host().connection_status {
+            ws.set_onstatus(|status| {
+                apply_update(|model| roc::frontend_connection_for_host(model, status.into()));
+            });
+        }
+        ws.connect().expect("Failed to connect to websocket");
     });
 }
 
+impl From<ConnectionStatus> for roc::HostConnectionStatus {
+    fn from(status: ConnectionStatus) -> Self {
+        let (kind, attempt, reason) = match status {
+            ConnectionStatus::Connecting => (0, 0, String::new()),
+            ConnectionStatus::Connected => (1, 0, String::new()),
+            ConnectionStatus::Reconnecting(attempt) => (2, attempt, String::new()),
+            ConnectionStatus::Disconnected(reason) => (3, 0, reason),
+        };
+
+        roc::HostConnectionStatus {
+            attempt,
+            reason: reason.as_str().into(),
+            kind,
+        }
+    }
+}
+
+/// Renders after every update instead of once per animation frame, so that tests can look
+/// at the DOM right after dispatching an event. A render that is waiting for its frame
+/// happens right away
+#[wasm_bindgen]
+pub fn set_sync_rendering(enabled: bool) {
+    SYNC_RENDERING.set(enabled);
+
+    if enabled && RENDER_SCHEDULED.replace(false) {
+        render_app();
+    }
+}
+
 fn get_ws_url() -> String {
     let window = web_sys::window().unwrap();
     let location = window.location();
@@ -73,276 +117,245 @@ fn get_ws_url() -> String {
     format!("{}//{}/ws", protocol, host)
 }
 
+// The kinds of `Internal.FrontendCmd.HostEffect`
+const EFFECT_DELAY: u8 = 0;
+const EFFECT_FOCUS: u8 = 1;
+const EFFECT_BLUR: u8 = 2;
+const EFFECT_SCROLL_INTO_VIEW: u8 = 3;
+const EFFECT_LOG: u8 = 4;
+
 fn render_app() {
-    let model = MODEL.lock().unwrap();
-
-    let app_html = roc::frontend_view_for_host(model.clone().inner);
-
-    match render_to_dom(app_html, "root") {
-        Ok(_) => {
-            console::log(
-                [JsValue::from("Render successful")]
-                    .iter()
-                    .collect::<Array>()
-                    .as_ref(),
-            );
-        }
-        Err(e) => {
-            console::log(
-                [JsValue::from("Render error:"), e]
-                    .iter()
-                    .collect::<Array>()
-                    .as_ref(),
-            );
+    let rendered = VDOM.with(|last_render| {
+        // A render that is already running picks up the new model once it is done
+        let Ok(mut last_render) = last_render.try_borrow_mut() else {
+            RERENDER.set(true);
+            return false;
+        };
+
+        loop {
+            RERENDER.set(false);
+            let model = MODEL.lock().unwrap().clone();
+            let app_html = roc::frontend_view_for_host(model.inner);
+
+            match render_to_dom(&mut last_render, &app_html, "root") {
+                Ok(_) => {
+                    console::log(
+                        [JsValue::from("Render successful")]
+                            .iter()
+                            .collect::<Array>()
+                            .as_ref(),
+                    );
+                }
+                Err(e) => {
+                    console::log(
+                        [JsValue::from("Render error:"), e]
+                            .iter()
+                            .collect::<Array>()
+                            .as_ref(),
+                    );
+                }
+            }
+
+            if !RERENDER.get() {
+                break;
+            }
         }
+
+        true
+    });
+
+    if rendered {
+        run_dom_effects();
     }
 }
 
-fn update_model_and_rerender(message: RocBox<()>) {
-    // TODO: Update the model with the message
-    {
-        let UpdateResult {
-            to_backend,
-            model: updated_model,
-        } = {
-            let model = MODEL.lock().expect("Unable to get model");
-            roc::frontend_update_for_host(model.clone().inner, message)
-        };
-        let updated_model = unsafe { Model::init(updated_model) };
-        let mut model = MODEL
-            .lock()
-            .expect("Could not acquire lock for model for update");
-        *model = updated_model;
-        drop(model);
-        console::debug(
-            [
-                JsValue::from("Rerendering"),
-                format!("To backend {:?}", &to_backend).into(),
-            ]
-            .iter()
-            .collect::<Array>()
-            .as_ref(),
-        );
-
-        if let Ok(to_backend_msg) = to_backend.into() {
-            WS.with(|ws: &RefCell<ReconnectingWebSocket>| {
-                let ws = ws.borrow();
-                ws.send_message(to_backend_msg.as_str()).unwrap();
-            });
+/// Patches the DOM rendered last time to match the new view. The first render replaces
+/// whatever the container held
+fn render_to_dom(
+    last_render: &mut Option<Mounted>,
+    html: &InternalHtml,
+    container_id: &str,
+) -> Result<(), JsValue> {
+    let window = web_sys::window().unwrap();
+    let document = window.document().unwrap();
+    let container = document
+        .get_element_by_id(container_id)
+        .ok_or("Container element not found")?;
+
+    let vnode = VNode::from_roc(html);
+    match last_render {
+        Some(mounted) => vdom::patch(&document, &container, mounted, vnode),
+        None => {
+            events::delegate_from(container.clone());
+            container.set_inner_html("");
+            let mounted = vdom::create(&document, vnode)?;
+            container.append_child(mounted.node())?;
+            *last_render = Some(mounted);
+
+            Ok(())
         }
     }
-
-    // Trigger re-render
-    render_app();
 }
 
-pub struct DomBuilder {
-    document: Document,
-    event_handlers: Vec<Closure<dyn FnMut(Event)>>,
+fn update_model_and_rerender(message: RocBox<()>) {
+    apply_update(|model| roc::frontend_update_for_host(model, message));
 }
 
-impl DomBuilder {
-    pub fn new(document: Document) -> Self {
-        Self {
-            document,
-            event_handlers: Vec::new(),
-        }
+/// Runs a roc update against the current model, sends the messages it produced to the
+/// backend and schedules a render
+fn apply_update(update: impl FnOnce(RocBox<()>) -> UpdateResult) {
+    let UpdateResult {
+        effects,
+        model: updated_model,
+        to_backend,
+    } = {
+        let model = MODEL.lock().expect("Unable to get model");
+        update(model.clone().inner)
+    };
+    let updated_model = unsafe { Model::init(updated_model) };
+    let mut model = MODEL
+        .lock()
+        .expect("Could not acquire lock for model for update");
+    *model = updated_model;
+    drop(model);
+    console::debug(
+        [
+            JsValue::from("Rerendering"),
+            format!("To backend {:?}", &to_backend).into(),
+        ]
+        .iter()
+        .collect::<Array>()
+        .as_ref(),
+    );
+
+    for message in to_backend.iter() {
+        send_to_backend(message);
     }
-
-    pub fn build_dom(
-        &mut self,
-        html: &InternalHtml,
-        parent_element: &Element,
-    ) -> Result<(), JsValue> {
-        // Clear existing content
-        parent_element.set_inner_html("");
-
-        match html.discriminant() {
-            discriminant_InternalHtml::Text => {
-                let text_content = html.get_Text_f0();
-                let text_node = self.document.create_text_node(text_content.as_str());
-                parent_element.append_child(&text_node)?;
-            }
-            discriminant_InternalHtml::Element => {
-                let element_data = html.get_Element_fields();
-                let element = self.build_element(element_data)?;
-                parent_element.append_child(&element)?;
-            }
-        }
-
-        Ok(())
+    for effect in effects.iter() {
+        run_effect(effect);
     }
 
-    fn build_element(
-        &mut self,
-        element_data: &InternalHtmlElementFields,
-    ) -> Result<web_sys::Node, JsValue> {
-        let tag_name = &element_data.tag;
-        let attrs = &element_data.attrs;
-        let children = &element_data.children;
-
-        // Create the element
-        let element = self.document.create_element(&tag_name.as_str())?;
-
-        // Apply attributes and event listeners
-        for i in 0..attrs.len() {
-            unsafe {
-                let elements_ptr = attrs.as_ptr() as *mut u8;
-                let attr: *mut InternalAttr =
-                    elements_ptr.add(InternalAttr::size() as usize * i).cast();
-
-                self.apply_attribute(&element, &*attr)?;
-            }
-        }
+    schedule_render();
+}
 
-        // Add children recursively
-        for child in children.iter() {
-            match child.discriminant() {
-                discriminant_InternalHtml::Text => {
-                    let text_content = child.get_Text_f0();
-                    let text_node = self.document.create_text_node(text_content.as_str());
-                    element.append_child(&text_node)?;
-                }
-                discriminant_InternalHtml::Element => {
-                    let element_data = child.get_Element_fields();
-                    let child_node = self.build_element(element_data)?;
-                    element.append_child(&child_node)?;
-                }
-            }
+fn run_effect(effect: &HostEffect) {
+    match effect.kind {
+        EFFECT_DELAY => {
+            let effect_id = effect.id;
+            let on_timeout = Closure::once_into_js(move || effect_done(effect_id, true));
+            let delay_ms = i32::try_from(effect.delay_ms).unwrap_or(i32::MAX);
+
+            web_sys::window()
+                .unwrap()
+                .set_timeout_with_callback_and_timeout_and_arguments_0(
+                    on_timeout.unchecked_ref(),
+                    delay_ms,
+                )
+                .expect("Could not start delay");
         }
-
-        Ok(element.into())
+        EFFECT_LOG => console::log_1(&effect.text.as_str().into()),
+        _ => DOM_EFFECTS.with_borrow_mut(|effects| effects.push(effect.clone())),
     }
+}
 
-    fn apply_attribute(&mut self, element: &Element, attr: &InternalAttr) -> Result<(), JsValue> {
-        match attr.discriminant() {
-            discriminant_InternalAttr::Id => {
-                element.set_attribute("id", &attr.borrow_Id().as_str())?;
-            }
-            discriminant_InternalAttr::Class => {
-                element.set_attribute("class", &attr.borrow_Class().as_str())?;
-            }
-            discriminant_InternalAttr::Value => {
-                element.set_attribute("value", &attr.borrow_Value().as_str())?;
-                // Also set the property for input elements
-                if let Some(input) = element.dyn_ref::<HtmlInputElement>() {
-                    input.set_value(&attr.borrow_Value().as_str());
-                }
-            }
-            discriminant_InternalAttr::Placeholder => {
-                element.set_attribute("placeholder", &attr.borrow_Placeholder().as_str())?;
-            }
-            discriminant_InternalAttr::Type => {
-                element.set_attribute("type", &attr.borrow_Type().as_str())?;
-            }
-            discriminant_InternalAttr::Name => {
-                element.set_attribute("name", &attr.borrow_Name().as_str())?;
-            }
-            discriminant_InternalAttr::Href => {
-                element.set_attribute("href", &attr.borrow_Href().as_str())?;
-            }
-            discriminant_InternalAttr::Src => {
-                element.set_attribute("src", &attr.borrow_Src().as_str())?;
-            }
-            discriminant_InternalAttr::Alt => {
-                element.set_attribute("alt", &attr.borrow_Alt().as_str())?;
-            }
-            discriminant_InternalAttr::Title => {
-                element.set_attribute("title", &attr.borrow_Title().as_str())?;
-            }
-            discriminant_InternalAttr::Style => {
-                element.set_attribute("style", &attr.borrow_Style().as_str())?;
-            }
-            discriminant_InternalAttr::Autocomplete => {
-                element.set_attribute("autocomplete", &attr.borrow_Autocomplete().as_str())?;
-            }
-            discriminant_InternalAttr::Tabindex => {
-                element.set_attribute("tabindex", &attr.borrow_Tabindex().to_string())?;
-            }
-
-            // Boolean attributes
-            discriminant_InternalAttr::Disabled => {
-                if attr.borrow_Disabled() {
-                    element.set_attribute("disabled", "")?;
-                }
-            }
-            discriminant_InternalAttr::Checked => {
-                if attr.borrow_Checked() {
-                    element.set_attribute("checked", "")?;
-                    if let Some(input) = element.dyn_ref::<HtmlInputElement>() {
-                        input.set_checked(true);
+/// Runs the effects on elements that were waiting for the render, and tells the app
+/// whether each found its element
+fn run_dom_effects() {
+    let effects = DOM_EFFECTS.take();
+    if effects.is_empty() {
+        return;
+    }
+    let document = web_sys::window().unwrap().document().unwrap();
+
+    for effect in effects {
+        let element = document.get_element_by_id(effect.text.as_str());
+        if let Some(element) = &element {
+            match effect.kind {
+                EFFECT_FOCUS => {
+                    if let Some(element) = element.dyn_ref::<HtmlElement>() {
+                        let _ = element.focus();
                     }
                 }
-            }
-            discriminant_InternalAttr::Selected => {
-                if attr.borrow_Selected() {
-                    element.set_attribute("selected", "")?;
-                }
-            }
-            discriminant_InternalAttr::Hidden => {
-                if attr.borrow_Hidden() {
-                    element.set_attribute("hidden", "")?;
-                }
-            }
-            discriminant_InternalAttr::Readonly => {
-                if attr.borrow_Readonly() {
-                    element.set_attribute("readonly", "")?;
-                }
-            }
-            discriminant_InternalAttr::Required => {
-                if attr.borrow_Required() {
-                    element.set_attribute("required", "")?;
-                }
-            }
-            discriminant_InternalAttr::Multiple => {
-                if attr.borrow_Multiple() {
-                    element.set_attribute("multiple", "")?;
+                EFFECT_BLUR => {
+                    if let Some(element) = element.dyn_ref::<HtmlElement>() {
+                        let _ = element.blur();
+                    }
                 }
+                EFFECT_SCROLL_INTO_VIEW => element.scroll_into_view(),
+                _ => {}
             }
+        }
 
-            // Data attributes
-            discriminant_InternalAttr::DataAttribute => {
-                let data_attr = attr.borrow_DataAttribute();
-                let key = format!("data-{}", data_attr.f0.as_str());
-                element.set_attribute(&key, &data_attr.f1.as_str())?;
-            }
+        if effect.id != 0 {
+            effect_done(effect.id, element.is_some());
+        }
+    }
+}
 
-            // Custom attributes
-            discriminant_InternalAttr::Attribute => {
-                let custom_attr = attr.borrow_Attribute();
-                element.set_attribute(&custom_attr.f0.as_str(), &custom_attr.f1.as_str())?;
-            }
+fn effect_done(effect_id: u64, found: bool) {
+    apply_update(|model| roc::frontend_effect_done_for_host(model, effect_id, found));
+}
 
-            // Event handlers - This is where the magic happens for rerenders!
-            discriminant_InternalAttr::OnEvent => {
-                let event = attr.borrow_OnEvent();
-                let event_type = event.event_type();
-                let mut cb = event.event_callback();
+/// Renders in the next animation frame, so that a burst of updates, e.g. messages arriving
+/// together over the websocket, only renders once
+fn schedule_render() {
+    if SYNC_RENDERING.get() {
+        render_app();
+        return;
+    }
+    if RENDER_SCHEDULED.replace(true) {
+        return;
+    }
 
-                let closure = Closure::wrap(Box::new(move |e: Event| {
-                    let internal_event = convert_web_event_to_internal(&e);
-                    let message = cb.force_thunk(internal_event);
+    let on_frame = Closure::once_into_js(|| {
+        // Cleared already if `set_sync_rendering` rendered in the meantime
+        if RENDER_SCHEDULED.replace(false) {
+            render_app();
+        }
+    });
+    if let Err(err) = web_sys::window()
+        .unwrap()
+        .request_animation_frame(on_frame.unchecked_ref())
+    {
+        console::error_2(&"Could not request an animation frame".into(), &err);
+        RENDER_SCHEDULED.set(false);
+        render_app();
+    }
+}
 
-                    update_model_and_rerender(message);
-                }) as Box<dyn FnMut(Event)>);
+/// Hands a message to the websocket, which queues it while disconnected, and starts the
+/// timeout for requests
+fn send_to_backend(message: &ToBackend) {
+    WS.with(|ws: &RefCell<ReconnectingWebSocket>| {
+        let ws = ws.borrow();
+        if let Err(err) = ws.send_message(message.request_id, message.message.as_str()) {
+            console::error_2(&"Could not send message to backend".into(), &err);
+        }
+    });
 
-                element.add_event_listener_with_callback(
-                    event_type.as_str(),
-                    closure.as_ref().unchecked_ref(),
-                )?;
+    if message.request_id != 0 && message.timeout_ms != 0 {
+        let request_id = message.request_id;
+        let on_timeout = Closure::once_into_js(move || {
+            apply_update(|model| roc::frontend_timeout_for_host(model, request_id));
+        });
+        let timeout_ms = i32::try_from(message.timeout_ms).unwrap_or(i32::MAX);
 
-                // Store the closure to keep it alive
-                self.event_handlers.push(closure);
-            }
-        }
-        Ok(())
+        web_sys::window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                on_timeout.unchecked_ref(),
+                timeout_ms,
+            )
+            .expect("Could not start request timeout");
     }
 }
 
-fn convert_web_event_to_internal(event: &Event) -> InternalEvent {
+/// `current_target` is the element whose handler is called, rather than the root the
+/// listener is on
+fn convert_web_event_to_internal(event: &Event, current_target: &Element) -> InternalEvent {
     // Get target information
     let target = get_target_info(event.target().as_ref());
-    let current_target = get_target_info(event.current_target().as_ref());
+    let current_target = get_target_info(Some(current_target.as_ref()));
 
     // Handle keyboard events
     let (key, code) = if let Some(keyboard_event) = event.dyn_ref::<web_sys::KeyboardEvent>() {
@@ -392,6 +405,7 @@ fn convert_web_event_to_internal(event: &Event) -> InternalEvent {
         metaKey: meta_key,
         preventDefault: false,
         stopPropagation: false,
+        values: roc_std::RocList::empty(),
     }
 }
 
@@ -420,22 +434,3 @@ fn get_target_info(target: Option<&web_sys::EventTarget>) -> R3 {
         R3::default()
     }
 }
-
-pub fn render_to_dom(html: InternalHtml, container_id: &str) -> Result<(), JsValue> {
-    let window = web_sys::window().unwrap();
-    let document = window.document().unwrap();
-    let container = document
-        .get_element_by_id(container_id)
-        .ok_or("Container element not found")?;
-
-    let mut builder = DomBuilder::new(document);
-    builder.build_dom(&html, &container)?;
-
-    // Keep the builder alive to maintain event handlers
-    // We need to store this somewhere so event handlers don't get dropped
-    BUILDER_STORAGE.with(|storage| {
-        *storage.borrow_mut() = Some(builder);
-    });
-
-    Ok(())
-}
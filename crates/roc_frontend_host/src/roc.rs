@@ -19,7 +19,7 @@ use std::alloc::{GlobalAlloc, Layout};
 use std::marker::{PhantomData, PhantomPinned};
 use std::mem::ManuallyDrop;
 
-use roc_std::{roc_refcounted_noop_impl, RocBox, RocRefcounted, RocResult, RocStr};
+use roc_std::{roc_refcounted_noop_impl, RocBox, RocList, RocRefcounted, RocStr};
 
 use crate::ALLOC;
 
@@ -75,6 +75,7 @@ pub unsafe extern "C" fn roc_dealloc(ptr: *mut c_void, alignment: u32) {
 }
 
 use web_sys::console;
+use web_sys::js_sys::{Array, Object, Reflect};
 
 #[no_mangle]
 pub unsafe extern "C" fn roc_panic(msg: *mut RocStr, tag_id: u32) {
@@ -93,8 +94,61 @@ pub unsafe extern "C" fn roc_panic(msg: *mut RocStr, tag_id: u32) {
 
 #[no_mangle]
 pub unsafe extern "C" fn roc_dbg(loc: *mut RocStr, msg: *mut RocStr, src: *mut RocStr) {
-    // TODO: Use console log
-    eprintln!("[{}] {} = {}", &*loc, &*src, &*msg);
+    console::debug_1(&format!("[{}] {} = {}", &*loc, &*src, &*msg).into());
+}
+
+/// A key/value pair given to `Log.info!` and friends
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct LogField {
+    pub f0: RocStr,
+    pub f1: RocStr,
+}
+
+impl RocRefcounted for LogField {
+    fn inc(&mut self) {
+        self.f0.inc();
+        self.f1.inc();
+    }
+    fn dec(&mut self) {
+        self.f0.dec();
+        self.f1.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+/// The fields are logged as an object after the message, so the console lets them be
+/// expanded and copied
+#[no_mangle]
+pub extern "C" fn roc_fx_log(level: u8, message: &RocStr, fields: &RocList<LogField>) {
+    let args = Array::of1(&message.as_str().into());
+    if !fields.is_empty() {
+        let object = Object::new();
+        for field in fields.iter() {
+            // Setting a property on a plain object cannot fail
+            let _ = Reflect::set(
+                &object,
+                &field.f0.as_str().into(),
+                &field.f1.as_str().into(),
+            );
+        }
+        args.push(&object);
+    }
+
+    match level {
+        0 => console::debug(&args),
+        1 => console::info(&args),
+        2 => console::warn(&args),
+        _ => console::error(&args),
+    }
+}
+
+/// Secrets stay on the backend, so `Env.var!` never finds anything in the browser
+#[no_mangle]
+pub extern "C" fn roc_fx_env_var(_name: &RocStr) -> roc_std::RocResult<RocStr, ()> {
+    roc_std::RocResult::err(())
 }
 
 #[no_mangle]
@@ -150,18 +204,68 @@ impl core::fmt::Debug for discriminant_RocResult {
 
 roc_refcounted_noop_impl!(discriminant_RocResult);
 
+/// A message the frontend's `update!` wants sent to the backend
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct ToBackend {
+    /// The id the backend's reply will carry, 0 when no reply is expected
+    pub request_id: u64,
+    /// 0 means the request never times out
+    pub timeout_ms: u64,
+    pub message: RocStr,
+}
+
+impl roc_std::RocRefcounted for ToBackend {
+    fn inc(&mut self) {
+        self.message.inc();
+    }
+    fn dec(&mut self) {
+        self.message.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+/// Something a command of the frontend's `update!` wants done in the browser, see
+/// `Internal.FrontendCmd.HostEffect`
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[repr(C)]
+pub struct HostEffect {
+    pub delay_ms: u64,
+    /// Reported back through `frontend_effect_done_for_host`, unless it is 0
+    pub id: u64,
+    pub text: RocStr,
+    pub kind: u8,
+}
+
+impl roc_std::RocRefcounted for HostEffect {
+    fn inc(&mut self) {
+        self.text.inc();
+    }
+    fn dec(&mut self) {
+        self.text.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
 #[repr(C)]
 pub struct UpdateResult {
+    pub effects: RocList<HostEffect>,
     pub model: RocBox<()>,
-    pub to_backend: RocResult<RocStr, ()>,
+    pub to_backend: RocList<ToBackend>,
 }
 
 impl roc_std::RocRefcounted for UpdateResult {
     fn inc(&mut self) {
+        self.effects.inc();
         self.to_backend.inc();
     }
     fn dec(&mut self) {
+        self.effects.dec();
         self.to_backend.dec();
     }
     fn is_refcounted() -> bool {
@@ -215,8 +319,49 @@ impl roc_std::RocRefcounted for R3 {
     }
 }
 
+/// A property of the event a handler asks for, see `Internal.Event.EventProperty`
 #[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
 #[repr(C)]
+pub struct EventProperty {
+    pub path: roc_std::RocList<roc_std::RocStr>,
+    pub kind: u8,
+    pub optional: bool,
+}
+
+impl roc_std::RocRefcounted for EventProperty {
+    fn inc(&mut self) {
+        self.path.inc();
+    }
+    fn dec(&mut self) {
+        self.path.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct HostEventValue {
+    pub number: f64,
+    pub text: roc_std::RocStr,
+    pub kind: u8,
+}
+
+impl roc_std::RocRefcounted for HostEventValue {
+    fn inc(&mut self) {
+        self.text.inc();
+    }
+    fn dec(&mut self) {
+        self.text.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, PartialOrd)]
+#[repr(C)]
 pub struct InternalEvent {
     pub button: i32,
     pub clientX: i32,
@@ -226,6 +371,7 @@ pub struct InternalEvent {
     pub eventType: roc_std::RocStr,
     pub key: roc_std::RocStr,
     pub target: R3,
+    pub values: roc_std::RocList<HostEventValue>,
     pub altKey: bool,
     pub ctrlKey: bool,
     pub metaKey: bool,
@@ -241,6 +387,7 @@ impl roc_std::RocRefcounted for InternalEvent {
         self.eventType.inc();
         self.key.inc();
         self.target.inc();
+        self.values.inc();
     }
     fn dec(&mut self) {
         self.code.dec();
@@ -248,12 +395,21 @@ impl roc_std::RocRefcounted for InternalEvent {
         self.eventType.dec();
         self.key.dec();
         self.target.dec();
+        self.values.dec();
     }
     fn is_refcounted() -> bool {
         true
     }
 }
 
+/// What an event handler returned
+#[repr(C)]
+pub struct EventResult {
+    pub message: RocBox<()>,
+    pub prevent_default: bool,
+    pub stop_propagation: bool,
+}
+
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct RocFunction {
@@ -261,12 +417,12 @@ pub struct RocFunction {
 }
 
 impl RocFunction {
-    pub fn force_thunk(&mut self, arg0: InternalEvent) -> RocBox<()> {
+    pub fn force_thunk(&mut self, arg0: InternalEvent) -> EventResult {
         extern "C" {
             fn roc__frontend_view_for_host_0_caller(
                 arg0: *const InternalEvent,
                 closure_data: *mut u8,
-                output: *mut RocBox<()>,
+                output: *mut EventResult,
             );
         }
 
@@ -285,6 +441,38 @@ impl RocFunction {
 }
 roc_refcounted_noop_impl!(RocFunction);
 
+/// The `{} -> InternalHtml msg` function of `Html.lazy`, copied out of roc's memory
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct RocThunk {
+    closure_data: Vec<u8>,
+}
+
+impl RocThunk {
+    pub fn force(&mut self) -> InternalHtml {
+        extern "C" {
+            fn roc__frontend_view_for_host_1_caller(
+                arg0: *const (),
+                closure_data: *mut u8,
+                output: *mut InternalHtml,
+            );
+        }
+
+        let mut output = core::mem::MaybeUninit::uninit();
+
+        unsafe {
+            roc__frontend_view_for_host_1_caller(
+                &(),
+                self.closure_data.as_mut_ptr(),
+                output.as_mut_ptr(),
+            );
+
+            output.assume_init()
+        }
+    }
+}
+roc_refcounted_noop_impl!(RocThunk);
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct InternalAttr_OnEvent {
@@ -308,7 +496,8 @@ impl InternalAttr_OnEvent {
         let roc_str_offset =
             (roc_function_size + roc_function_align - 1) & !(roc_function_align - 1);
 
-        let on_event_unaligned_size = size_of::<RocStr>() + roc_str_offset;
+        let on_event_unaligned_size =
+            roc_str_offset + size_of::<RocStr>() + size_of::<RocList<EventProperty>>();
 
         (on_event_unaligned_size + roc_function_align - 1) & !(roc_function_align - 1)
     }
@@ -324,6 +513,21 @@ impl InternalAttr_OnEvent {
         ManuallyDrop::new(unsafe { std::ptr::read(roc_str_ptr as *const RocStr) })
     }
 
+    /// The properties the handler wants read from the event, they follow the event type
+    pub fn event_properties(&self) -> ManuallyDrop<RocList<EventProperty>> {
+        let roc_function_size = InternalAttr_OnEvent::closure_data_size();
+        let roc_function_align = InternalAttr::ALIGN;
+        let roc_str_offset =
+            (roc_function_size + roc_function_align - 1) & !(roc_function_align - 1);
+        let properties_offset = roc_str_offset + size_of::<RocStr>();
+
+        let properties_ptr = unsafe { (self as *const _ as *const u8).add(properties_offset) };
+
+        ManuallyDrop::new(unsafe {
+            std::ptr::read(properties_ptr as *const RocList<EventProperty>)
+        })
+    }
+
     pub fn event_callback(&self) -> RocFunction {
         let closure_data = unsafe {
             std::ptr::slice_from_raw_parts(self as *const _ as *const u8, Self::closure_data_size())
@@ -960,6 +1164,107 @@ impl roc_std::RocRefcounted for InternalHtml_Element {
     }
 }
 
+/// A child of `Html.keyed` together with its key
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub struct InternalHtmlKeyedChild {
+    pub f0: roc_std::RocStr,
+    pub f1: InternalHtml,
+}
+
+impl roc_std::RocRefcounted for InternalHtmlKeyedChild {
+    fn inc(&mut self) {
+        self.f0.inc();
+        self.f1.inc();
+    }
+    fn dec(&mut self) {
+        self.f0.dec();
+        self.f1.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub struct InternalHtmlKeyedFields {
+    pub attrs: roc_std::RocList<InternalAttr>,
+    pub children: roc_std::RocList<InternalHtmlKeyedChild>,
+    pub tag: roc_std::RocStr,
+}
+
+impl roc_std::RocRefcounted for InternalHtmlKeyedFields {
+    fn inc(&mut self) {
+        self.attrs.inc();
+        self.children.inc();
+        self.tag.inc();
+    }
+    fn dec(&mut self) {
+        self.attrs.dec();
+        self.children.dec();
+        self.tag.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Debug)]
+#[repr(transparent)]
+pub struct InternalHtml_Keyed {
+    pub fields: InternalHtmlKeyedFields,
+}
+
+impl roc_std::RocRefcounted for InternalHtml_Keyed {
+    fn inc(&mut self) {
+        self.fields.inc();
+    }
+    fn dec(&mut self) {
+        self.fields.dec();
+    }
+    fn is_refcounted() -> bool {
+        true
+    }
+}
+
+/// The payload of `Lazy`. Its size depends on what the app's lazy views capture, so it is
+/// only ever accessed through a reference into roc's memory
+#[derive(Debug)]
+#[repr(C)]
+pub struct InternalHtml_Lazy {
+    data: (),
+    _marker: PhantomData<(*mut u8, PhantomPinned)>,
+}
+
+impl InternalHtml_Lazy {
+    fn closure_data_size() -> usize {
+        extern "C" {
+            #[link_name = "roc__frontend_view_for_host_1_size"]
+            fn roc_closure_size() -> u64;
+        }
+        unsafe { roc_closure_size() as usize }
+    }
+
+    /// The key the app gave the lazy view
+    pub fn key(&self) -> &RocStr {
+        let key_align = core::mem::align_of::<RocStr>();
+        let key_offset = (Self::closure_data_size() + key_align - 1) & !(key_align - 1);
+
+        unsafe { &*(self as *const _ as *const u8).add(key_offset).cast() }
+    }
+
+    pub fn thunk(&self) -> RocThunk {
+        let closure_data = unsafe {
+            std::slice::from_raw_parts(self as *const _ as *const u8, Self::closure_data_size())
+        };
+
+        RocThunk {
+            closure_data: Vec::from(closure_data),
+        }
+    }
+}
+
 #[derive(Clone, Default, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
 #[repr(transparent)]
 pub struct InternalHtml_Text {
@@ -982,13 +1287,17 @@ impl roc_std::RocRefcounted for InternalHtml_Text {
 #[repr(u8)]
 pub enum discriminant_InternalHtml {
     Element = 0,
-    Text = 1,
+    Keyed = 1,
+    Lazy = 2,
+    Text = 3,
 }
 
 impl core::fmt::Debug for discriminant_InternalHtml {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Element => f.write_str("discriminant_InternalHtml::Element"),
+            Self::Keyed => f.write_str("discriminant_InternalHtml::Keyed"),
+            Self::Lazy => f.write_str("discriminant_InternalHtml::Lazy"),
             Self::Text => f.write_str("discriminant_InternalHtml::Text"),
         }
     }
@@ -1004,7 +1313,7 @@ impl InternalHtml {
         let discriminants = {
             use discriminant_InternalHtml::*;
 
-            [Element, Text]
+            [Element, Keyed, Lazy, Text]
         };
 
         if self.0.is_null() {
@@ -1070,6 +1379,26 @@ impl InternalHtml {
         unsafe { core::mem::ManuallyDrop::take(&mut self.ptr_read_union().Element) }
     }
 
+    pub fn is_Keyed(&self) -> bool {
+        matches!(self.discriminant(), discriminant_InternalHtml::Keyed)
+    }
+
+    pub fn get_Keyed_fields(&self) -> &InternalHtmlKeyedFields {
+        debug_assert!(self.is_Keyed());
+
+        unsafe { &*self.unmasked_pointer().cast() }
+    }
+
+    pub fn is_Lazy(&self) -> bool {
+        matches!(self.discriminant(), discriminant_InternalHtml::Lazy)
+    }
+
+    pub fn get_Lazy(&self) -> &InternalHtml_Lazy {
+        debug_assert!(self.is_Lazy());
+
+        unsafe { &*self.unmasked_pointer().cast() }
+    }
+
     pub fn is_Text(&self) -> bool {
         matches!(self.discriminant(), discriminant_InternalHtml::Text)
     }
@@ -1126,6 +1455,20 @@ impl Clone for InternalHtml {
 
                 Self((ptr as usize | tag_id as usize) as *mut _)
             }
+            Keyed => {
+                let tag_id = discriminant_InternalHtml::Keyed;
+
+                let payload_union = unsafe { self.ptr_read_union() };
+                let payload = union_InternalHtml {
+                    Keyed: unsafe { payload_union.Keyed.clone() },
+                };
+
+                let ptr = unsafe { roc_std::RocBox::leak(roc_std::RocBox::new(payload)) };
+
+                Self((ptr as usize | tag_id as usize) as *mut _)
+            }
+            // The payload is not a fixed size, the copy shares it instead
+            Lazy => Self(self.0),
             Text => {
                 let tag_id = discriminant_InternalHtml::Text;
 
@@ -1156,6 +1499,19 @@ impl core::fmt::Debug for InternalHtml {
                         .finish()
                 }
             }
+            Keyed => {
+                let payload_union = unsafe { self.ptr_read_union() };
+
+                unsafe {
+                    f.debug_tuple("InternalHtml::Keyed")
+                        .field(&payload_union.Keyed.fields)
+                        .finish()
+                }
+            }
+            Lazy => f
+                .debug_tuple("InternalHtml::Lazy")
+                .field(self.get_Lazy().key())
+                .finish(),
             Text => {
                 let payload_union = unsafe { self.ptr_read_union() };
 
@@ -1172,6 +1528,8 @@ impl core::fmt::Debug for InternalHtml {
 #[repr(C)]
 union union_InternalHtml {
     Element: core::mem::ManuallyDrop<InternalHtml_Element>,
+    Keyed: core::mem::ManuallyDrop<InternalHtml_Keyed>,
+    Lazy: core::mem::ManuallyDrop<InternalHtml_Lazy>,
     Text: core::mem::ManuallyDrop<InternalHtml_Text>,
 }
 
@@ -1199,6 +1557,39 @@ impl roc_std::RocRefcounted for union_InternalHtml {
     }
 }
 
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct FrontendConfig {
+    /// Whether the app wants to hear about the websocket connecting and disconnecting
+    pub connection_status: bool,
+    /// Whether messages waiting for the websocket are kept in `localStorage`
+    pub offline_storage: bool,
+}
+
+/// The state of the websocket as the roc frontend expects it. `kind` is 0 for connecting,
+/// 1 for connected, 2 for reconnecting and 3 for disconnected
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct HostConnectionStatus {
+    pub attempt: u32,
+    pub reason: RocStr,
+    pub kind: u8,
+}
+
+pub fn frontend_config_for_host() -> FrontendConfig {
+    extern "C" {
+        fn roc__frontend_config_for_host_1_exposed_generic(_: *mut FrontendConfig, _: i32);
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__frontend_config_for_host_1_exposed_generic(ret.as_mut_ptr(), 0);
+
+        ret.assume_init()
+    }
+}
+
 pub fn frontend_init_for_host(arg0: u32) -> RocBox<()> {
     extern "C" {
         fn roc__frontend_init_for_host_1_exposed_generic(_: *mut RocBox<()>, _: u32);
@@ -1245,22 +1636,95 @@ pub fn frontend_update_for_host(model: RocBox<()>, boxed_msg: RocBox<()>) -> Upd
     }
 }
 
-pub fn frontend_decode_to_frontend_msg(msg_bytes: roc_std::RocList<u8>) -> RocBox<()> {
+pub fn frontend_receive_for_host(model: RocBox<()>, msg_bytes: RocList<u8>) -> UpdateResult {
     extern "C" {
-        fn roc__frontend_decode_to_frontend_msg_1_exposed_generic(
-            _: *mut RocBox<()>,
-            _: &mut core::mem::ManuallyDrop<roc_std::RocList<u8>>,
+        fn roc__frontend_receive_for_host_1_exposed_generic(
+            _: *mut UpdateResult,
+            _: RocBox<()>,
+            _: &mut core::mem::ManuallyDrop<RocList<u8>>,
         );
     }
 
     let mut ret = core::mem::MaybeUninit::uninit();
 
     unsafe {
-        roc__frontend_decode_to_frontend_msg_1_exposed_generic(
+        roc__frontend_receive_for_host_1_exposed_generic(
             ret.as_mut_ptr(),
+            model,
             &mut core::mem::ManuallyDrop::new(msg_bytes),
         );
 
         ret.assume_init()
     }
 }
+
+pub fn frontend_connection_for_host(
+    model: RocBox<()>,
+    status: HostConnectionStatus,
+) -> UpdateResult {
+    extern "C" {
+        fn roc__frontend_connection_for_host_1_exposed_generic(
+            _: *mut UpdateResult,
+            _: RocBox<()>,
+            _: &mut core::mem::ManuallyDrop<HostConnectionStatus>,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__frontend_connection_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            model,
+            &mut core::mem::ManuallyDrop::new(status),
+        );
+
+        ret.assume_init()
+    }
+}
+
+pub fn frontend_timeout_for_host(model: RocBox<()>, request_id: u64) -> UpdateResult {
+    extern "C" {
+        fn roc__frontend_timeout_for_host_1_exposed_generic(
+            _: *mut UpdateResult,
+            _: RocBox<()>,
+            _: u64,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__frontend_timeout_for_host_1_exposed_generic(ret.as_mut_ptr(), model, request_id);
+
+        ret.assume_init()
+    }
+}
+
+pub fn frontend_effect_done_for_host(
+    model: RocBox<()>,
+    effect_id: u64,
+    found: bool,
+) -> UpdateResult {
+    extern "C" {
+        fn roc__frontend_effect_done_for_host_1_exposed_generic(
+            _: *mut UpdateResult,
+            _: RocBox<()>,
+            _: u64,
+            _: bool,
+        );
+    }
+
+    let mut ret = core::mem::MaybeUninit::uninit();
+
+    unsafe {
+        roc__frontend_effect_done_for_host_1_exposed_generic(
+            ret.as_mut_ptr(),
+            model,
+            effect_id,
+            found,
+        );
+
+        ret.assume_init()
+    }
+}
@@ -0,0 +1,725 @@
+//! A copy of the last rendered view kept next to the DOM it was rendered to. Each render
+//! is compared against it and only what changed is touched, so focus, the cursor, scroll
+//! offsets and running transitions survive a rerender
+
+use std::collections::{HashMap, HashSet};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, HtmlInputElement, Node, Text};
+
+use roc_std::{RocList, RocStr};
+
+use crate::events::{Handler, Handlers, Property};
+use crate::roc::{
+    discriminant_InternalAttr, discriminant_InternalHtml, InternalAttr, InternalHtml, RocThunk,
+};
+
+/// The view as plain rust data, copied out of roc's memory
+pub enum VNode {
+    Text(String),
+    Element(VElement),
+    /// Only turned into nodes when the key differs from the one mounted at the same spot
+    Lazy {
+        key: String,
+        thunk: RocThunk,
+    },
+}
+
+pub struct VElement {
+    tag: String,
+    attrs: Vec<(String, String)>,
+    /// Kept in sync with the live `value` property, which the attribute stops affecting as
+    /// soon as the user types
+    value: Option<String>,
+    /// Likewise for the `checked` property
+    checked: Option<bool>,
+    events: Vec<Handler>,
+    children: Vec<VNode>,
+    /// Set for `Html.keyed` elements, one key per child
+    keys: Option<Vec<String>>,
+}
+
+/// A node of the last render together with the DOM node it became
+pub enum Mounted {
+    Text { text: String, node: Text },
+    Element(MountedElement),
+    Lazy { key: String, child: Box<Mounted> },
+}
+
+pub struct MountedElement {
+    tag: String,
+    attrs: Vec<(String, String)>,
+    value: Option<String>,
+    checked: Option<bool>,
+    /// Unset while the element handles no events
+    handlers: Option<Handlers>,
+    children: Vec<Mounted>,
+    keys: Option<Vec<String>>,
+    element: Element,
+}
+
+impl VNode {
+    pub fn from_roc(html: &InternalHtml) -> Self {
+        match html.discriminant() {
+            discriminant_InternalHtml::Text => VNode::Text(html.get_Text_f0().as_str().to_owned()),
+            discriminant_InternalHtml::Element => {
+                let fields = html.get_Element_fields();
+                let children = fields.children.iter().map(VNode::from_roc).collect();
+                VNode::Element(VElement::from_roc(
+                    &fields.tag,
+                    &fields.attrs,
+                    children,
+                    None,
+                ))
+            }
+            discriminant_InternalHtml::Keyed => {
+                let fields = html.get_Keyed_fields();
+                let (keys, children) = fields
+                    .children
+                    .iter()
+                    .map(|child| (child.f0.as_str().to_owned(), VNode::from_roc(&child.f1)))
+                    .unzip();
+                VNode::Element(VElement::from_roc(
+                    &fields.tag,
+                    &fields.attrs,
+                    children,
+                    Some(keys),
+                ))
+            }
+            discriminant_InternalHtml::Lazy => {
+                let lazy = html.get_Lazy();
+                VNode::Lazy {
+                    key: lazy.key().as_str().to_owned(),
+                    thunk: lazy.thunk(),
+                }
+            }
+        }
+    }
+}
+
+impl VElement {
+    fn from_roc(
+        tag: &RocStr,
+        attrs: &RocList<InternalAttr>,
+        children: Vec<VNode>,
+        keys: Option<Vec<String>>,
+    ) -> Self {
+        let mut element = VElement {
+            tag: tag.as_str().to_owned(),
+            attrs: Vec::new(),
+            value: None,
+            checked: None,
+            events: Vec::new(),
+            children,
+            keys,
+        };
+
+        for i in 0..attrs.len() {
+            unsafe {
+                let elements_ptr = attrs.as_ptr() as *mut u8;
+                let attr: *mut InternalAttr = elements_ptr.add(InternalAttr::size() * i).cast();
+
+                element.add_attribute(&*attr);
+            }
+        }
+
+        element
+    }
+
+    fn add_attribute(&mut self, attr: &InternalAttr) {
+        let mut set = |name: &str, value: &str| {
+            self.attrs.push((name.to_owned(), value.to_owned()));
+        };
+
+        match attr.discriminant() {
+            discriminant_InternalAttr::Id => set("id", attr.borrow_Id().as_str()),
+            discriminant_InternalAttr::Class => set("class", attr.borrow_Class().as_str()),
+            discriminant_InternalAttr::Value => {
+                set("value", attr.borrow_Value().as_str());
+                self.value = Some(attr.borrow_Value().as_str().to_owned());
+            }
+            discriminant_InternalAttr::Placeholder => {
+                set("placeholder", attr.borrow_Placeholder().as_str())
+            }
+            discriminant_InternalAttr::Type => set("type", attr.borrow_Type().as_str()),
+            discriminant_InternalAttr::Name => set("name", attr.borrow_Name().as_str()),
+            discriminant_InternalAttr::Href => set("href", attr.borrow_Href().as_str()),
+            discriminant_InternalAttr::Src => set("src", attr.borrow_Src().as_str()),
+            discriminant_InternalAttr::Alt => set("alt", attr.borrow_Alt().as_str()),
+            discriminant_InternalAttr::Title => set("title", attr.borrow_Title().as_str()),
+            discriminant_InternalAttr::Style => set("style", attr.borrow_Style().as_str()),
+            discriminant_InternalAttr::Autocomplete => {
+                set("autocomplete", attr.borrow_Autocomplete().as_str())
+            }
+            discriminant_InternalAttr::Tabindex => {
+                set("tabindex", &attr.borrow_Tabindex().to_string())
+            }
+
+            // Boolean attributes are left out when false
+            discriminant_InternalAttr::Disabled => {
+                if attr.borrow_Disabled() {
+                    set("disabled", "");
+                }
+            }
+            discriminant_InternalAttr::Checked => {
+                if attr.borrow_Checked() {
+                    set("checked", "");
+                }
+                self.checked = Some(attr.borrow_Checked());
+            }
+            discriminant_InternalAttr::Selected => {
+                if attr.borrow_Selected() {
+                    set("selected", "");
+                }
+            }
+            discriminant_InternalAttr::Hidden => {
+                if attr.borrow_Hidden() {
+                    set("hidden", "");
+                }
+            }
+            discriminant_InternalAttr::Readonly => {
+                if attr.borrow_Readonly() {
+                    set("readonly", "");
+                }
+            }
+            discriminant_InternalAttr::Required => {
+                if attr.borrow_Required() {
+                    set("required", "");
+                }
+            }
+            discriminant_InternalAttr::Multiple => {
+                if attr.borrow_Multiple() {
+                    set("multiple", "");
+                }
+            }
+
+            discriminant_InternalAttr::DataAttribute => {
+                let data_attr = attr.borrow_DataAttribute();
+                set(
+                    &format!("data-{}", data_attr.f0.as_str()),
+                    data_attr.f1.as_str(),
+                );
+            }
+            discriminant_InternalAttr::Attribute => {
+                let custom_attr = attr.borrow_Attribute();
+                set(custom_attr.f0.as_str(), custom_attr.f1.as_str());
+            }
+
+            discriminant_InternalAttr::OnEvent => {
+                let event = attr.borrow_OnEvent();
+                self.events.push(Handler {
+                    event_type: event.event_type().as_str().to_owned(),
+                    properties: event
+                        .event_properties()
+                        .iter()
+                        .map(Property::from_roc)
+                        .collect(),
+                    callback: event.event_callback(),
+                });
+            }
+        }
+    }
+}
+
+impl Mounted {
+    pub fn node(&self) -> &Node {
+        match self {
+            Mounted::Text { node, .. } => node,
+            Mounted::Element(mounted) => &mounted.element,
+            Mounted::Lazy { child, .. } => child.node(),
+        }
+    }
+}
+
+/// Creates the DOM for a node that was not rendered before
+pub fn create(document: &Document, vnode: VNode) -> Result<Mounted, JsValue> {
+    match vnode {
+        VNode::Text(text) => Ok(Mounted::Text {
+            node: document.create_text_node(&text),
+            text,
+        }),
+        VNode::Element(velement) => {
+            let element = document.create_element(&velement.tag)?;
+            for (name, value) in &velement.attrs {
+                element.set_attribute(name, value)?;
+            }
+            if let Some(input) = element.dyn_ref::<HtmlInputElement>() {
+                if let Some(value) = &velement.value {
+                    input.set_value(value);
+                }
+                if let Some(checked) = velement.checked {
+                    input.set_checked(checked);
+                }
+            }
+
+            let handlers = handle_events(&element, None, velement.events)?;
+
+            let mut children = Vec::with_capacity(velement.children.len());
+            for child in velement.children {
+                let child = create(document, child)?;
+                element.append_child(child.node())?;
+                children.push(child);
+            }
+
+            Ok(Mounted::Element(MountedElement {
+                tag: velement.tag,
+                attrs: velement.attrs,
+                value: velement.value,
+                checked: velement.checked,
+                handlers,
+                children,
+                keys: velement.keys,
+                element,
+            }))
+        }
+        VNode::Lazy { key, mut thunk } => {
+            let child = create(document, VNode::from_roc(&thunk.force()))?;
+            Ok(Mounted::Lazy {
+                key,
+                child: Box::new(child),
+            })
+        }
+    }
+}
+
+/// Brings the DOM of `mounted` in line with `vnode`, replacing it only when the two are not
+/// the same kind of node
+pub fn patch(
+    document: &Document,
+    parent: &Node,
+    mounted: &mut Mounted,
+    vnode: VNode,
+) -> Result<(), JsValue> {
+    match (&mut *mounted, vnode) {
+        (Mounted::Text { text, node }, VNode::Text(new_text)) => {
+            if *text != new_text {
+                node.set_data(&new_text);
+                *text = new_text;
+            }
+        }
+        (Mounted::Element(old), VNode::Element(new)) if old.tag == new.tag => {
+            patch_element(document, old, new)?;
+        }
+        (
+            Mounted::Lazy { key, child },
+            VNode::Lazy {
+                key: new_key,
+                mut thunk,
+            },
+        ) => {
+            patch_lazy(key, new_key, || {
+                patch(document, parent, child, VNode::from_roc(&thunk.force()))
+            })?;
+        }
+        (_, vnode) => {
+            let replacement = create(document, vnode)?;
+            parent.replace_child(replacement.node(), mounted.node())?;
+            *mounted = replacement;
+        }
+    }
+
+    Ok(())
+}
+
+/// Calls `render`, and with it the view of a lazy node, only when its key changed since the
+/// last render
+fn patch_lazy<E>(
+    key: &mut String,
+    new_key: String,
+    render: impl FnOnce() -> Result<(), E>,
+) -> Result<(), E> {
+    if *key != new_key {
+        render()?;
+        *key = new_key;
+    }
+
+    Ok(())
+}
+
+fn patch_element(
+    document: &Document,
+    mounted: &mut MountedElement,
+    velement: VElement,
+) -> Result<(), JsValue> {
+    let element = &mounted.element;
+
+    for (name, _) in &mounted.attrs {
+        if !velement.attrs.iter().any(|(new_name, _)| new_name == name) {
+            element.remove_attribute(name)?;
+        }
+    }
+    for (name, value) in &velement.attrs {
+        let unchanged = mounted
+            .attrs
+            .iter()
+            .any(|(old_name, old_value)| old_name == name && old_value == value);
+        if !unchanged {
+            element.set_attribute(name, value)?;
+        }
+    }
+    mounted.attrs = velement.attrs;
+
+    // Compared with the live properties, since the user changes them without a render
+    if let Some(input) = element.dyn_ref::<HtmlInputElement>() {
+        if let Some(value) = &velement.value {
+            if input.value() != *value {
+                input.set_value(value);
+            }
+        }
+        match (mounted.checked, velement.checked) {
+            (_, Some(checked)) if input.checked() != checked => input.set_checked(checked),
+            (Some(_), None) => input.set_checked(false),
+            _ => {}
+        }
+    }
+    mounted.value = velement.value;
+    mounted.checked = velement.checked;
+
+    mounted.handlers = handle_events(element, mounted.handlers.take(), velement.events)?;
+
+    match (&mounted.keys, velement.keys) {
+        (Some(old_keys), Some(new_keys)) => {
+            patch_keyed_children(
+                document,
+                element,
+                &mut mounted.children,
+                old_keys,
+                velement.children,
+                &new_keys,
+            )?;
+            mounted.keys = Some(new_keys);
+        }
+        (_, new_keys) => {
+            patch_children(document, element, &mut mounted.children, velement.children)?;
+            mounted.keys = new_keys;
+        }
+    }
+
+    Ok(())
+}
+
+/// Children are matched up by their position
+fn patch_children(
+    document: &Document,
+    parent: &Element,
+    mounted: &mut Vec<Mounted>,
+    vnodes: Vec<VNode>,
+) -> Result<(), JsValue> {
+    match_by_position(
+        mounted,
+        vnodes,
+        |child, vnode| patch(document, parent, child, vnode),
+        |vnode| {
+            let child = create(document, vnode)?;
+            parent.append_child(child.node())?;
+            Ok(child)
+        },
+        |child| parent.remove_child(child.node()).map(drop),
+    )
+}
+
+/// Removes the children past the end of the new list, patches the ones both lists have and
+/// creates the rest, in that order
+fn match_by_position<M, V, E>(
+    mounted: &mut Vec<M>,
+    vnodes: Vec<V>,
+    mut patch: impl FnMut(&mut M, V) -> Result<(), E>,
+    mut create: impl FnMut(V) -> Result<M, E>,
+    mut remove: impl FnMut(M) -> Result<(), E>,
+) -> Result<(), E> {
+    if mounted.len() > vnodes.len() {
+        for child in mounted.drain(vnodes.len()..) {
+            remove(child)?;
+        }
+    }
+
+    let mut vnodes = vnodes.into_iter();
+    for (child, vnode) in mounted.iter_mut().zip(vnodes.by_ref()) {
+        patch(child, vnode)?;
+    }
+    for vnode in vnodes {
+        mounted.push(create(vnode)?);
+    }
+
+    Ok(())
+}
+
+/// Children are matched up by their key. Nodes whose key is still there are patched and
+/// moved where they now belong, the others are removed or created. Only the nodes outside
+/// the longest run already in order are moved, so reordering a few items of a long list
+/// touches just those items
+fn patch_keyed_children(
+    document: &Document,
+    parent: &Element,
+    mounted: &mut Vec<Mounted>,
+    old_keys: &[String],
+    vnodes: Vec<VNode>,
+    new_keys: &[String],
+) -> Result<(), JsValue> {
+    let mut old_indices = HashMap::with_capacity(old_keys.len());
+    for (i, key) in old_keys.iter().enumerate() {
+        // With duplicate keys only the first one is reused
+        old_indices.entry(key.as_str()).or_insert(i);
+    }
+
+    let mut old: Vec<Option<Mounted>> = std::mem::take(mounted).into_iter().map(Some).collect();
+    let mut children = Vec::with_capacity(vnodes.len());
+    // For each new child the index it had before, if it was kept
+    let mut sources = Vec::with_capacity(vnodes.len());
+
+    for (key, vnode) in new_keys.iter().zip(vnodes) {
+        let reused = old_indices
+            .remove(key.as_str())
+            .and_then(|i| old[i].take().map(|child| (i, child)));
+        match reused {
+            Some((i, mut child)) => {
+                patch(document, parent, &mut child, vnode)?;
+                children.push(child);
+                sources.push(Some(i));
+            }
+            None => {
+                children.push(create(document, vnode)?);
+                sources.push(None);
+            }
+        }
+    }
+
+    for child in old.into_iter().flatten() {
+        parent.remove_child(child.node())?;
+    }
+
+    let in_order = longest_increasing_run(&sources);
+    let mut next: Option<Node> = None;
+    for (i, child) in children.iter().enumerate().rev() {
+        if !in_order.contains(&i) {
+            parent.insert_before(child.node(), next.as_ref())?;
+        }
+        next = Some(child.node().clone());
+    }
+
+    *mounted = children;
+    Ok(())
+}
+
+/// Positions of the longest subsequence of kept children whose old indices are increasing,
+/// those can stay where they are
+fn longest_increasing_run(sources: &[Option<usize>]) -> HashSet<usize> {
+    // tails[k] is the position ending the best run of length k + 1 found so far
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; sources.len()];
+
+    for (i, source) in sources.iter().enumerate() {
+        let Some(source) = *source else { continue };
+        let length = tails.partition_point(|&tail| sources[tail] < Some(source));
+        if length > 0 {
+            previous[i] = Some(tails[length - 1]);
+        }
+        if length == tails.len() {
+            tails.push(i);
+        } else {
+            tails[length] = i;
+        }
+    }
+
+    let mut run = HashSet::with_capacity(tails.len());
+    let mut current = tails.last().copied();
+    while let Some(i) = current {
+        run.insert(i);
+        current = previous[i];
+    }
+    run
+}
+
+/// Registers the handlers of an element with the root's listeners, reusing its entry from
+/// the last render
+fn handle_events(
+    element: &Element,
+    handlers: Option<Handlers>,
+    events: Vec<Handler>,
+) -> Result<Option<Handlers>, JsValue> {
+    if events.is_empty() {
+        return Ok(None);
+    }
+
+    let handlers = match handlers {
+        Some(handlers) => handlers,
+        None => Handlers::register(element)?,
+    };
+    handlers.set(events)?;
+
+    Ok(Some(handlers))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    /// Matches up numbered children, recording what happens to them
+    fn match_numbers(mounted: &mut Vec<u32>, new: Vec<u32>) -> Vec<String> {
+        let log = RefCell::new(Vec::new());
+        match_by_position::<_, _, ()>(
+            mounted,
+            new,
+            |child, vnode| {
+                log.borrow_mut().push(format!("patch {child} to {vnode}"));
+                *child = vnode;
+                Ok(())
+            },
+            |vnode| {
+                log.borrow_mut().push(format!("create {vnode}"));
+                Ok(vnode)
+            },
+            |child| {
+                log.borrow_mut().push(format!("remove {child}"));
+                Ok(())
+            },
+        )
+        .unwrap();
+        log.into_inner()
+    }
+
+    fn run(sources: &[Option<usize>]) -> Vec<usize> {
+        let mut run: Vec<_> = longest_increasing_run(sources).into_iter().collect();
+        run.sort_unstable();
+        run
+    }
+
+    /// The length of the longest increasing run, the slow way
+    fn longest_run_len(sources: &[Option<usize>]) -> usize {
+        let mut lengths = vec![0; sources.len()];
+        for i in 0..sources.len() {
+            if sources[i].is_some() {
+                lengths[i] = 1
+                    + (0..i)
+                        .filter(|&j| sources[j].is_some() && sources[j] < sources[i])
+                        .map(|j| lengths[j])
+                        .max()
+                        .unwrap_or(0);
+            }
+        }
+        lengths.into_iter().max().unwrap_or(0)
+    }
+
+    /// Patches a lazy node from `key` to `new_key`, returning how often its view was called
+    fn renders(key: &str, new_key: &str) -> (usize, String) {
+        let mut key = key.to_string();
+        let mut calls = 0;
+        patch_lazy::<()>(&mut key, new_key.to_string(), || {
+            calls += 1;
+            Ok(())
+        })
+        .unwrap();
+        (calls, key)
+    }
+
+    #[test]
+    fn an_unchanged_key_skips_the_view() {
+        assert_eq!(
+            renders("{\"rows\":[1,2]}", "{\"rows\":[1,2]}"),
+            (0, "{\"rows\":[1,2]}".into())
+        );
+    }
+
+    #[test]
+    fn a_changed_key_calls_the_view_and_is_kept() {
+        assert_eq!(
+            renders("{\"rows\":[1,2]}", "{\"rows\":[1,3]}"),
+            (1, "{\"rows\":[1,3]}".into())
+        );
+    }
+
+    #[test]
+    fn a_failed_render_keeps_the_old_key() {
+        let mut key = "1".to_string();
+        assert!(patch_lazy(&mut key, "2".to_string(), || Err(())).is_err());
+        assert_eq!(key, "1");
+    }
+
+    #[test]
+    fn nothing_stays_in_an_empty_list() {
+        assert!(run(&[]).is_empty());
+        assert!(run(&[None, None]).is_empty());
+    }
+
+    #[test]
+    fn children_in_order_all_stay() {
+        assert_eq!(run(&[Some(0), Some(1), Some(2)]), [0, 1, 2]);
+        assert_eq!(run(&[Some(0), None, Some(4), None]), [0, 2]);
+    }
+
+    #[test]
+    fn only_the_moved_children_are_left_out() {
+        // The last child moved to the front
+        assert_eq!(run(&[Some(3), Some(0), Some(1), Some(2)]), [1, 2, 3]);
+        // The first child moved to the back
+        assert_eq!(run(&[Some(1), Some(2), Some(3), Some(0)]), [0, 1, 2]);
+        // Two children swapped
+        assert_eq!(run(&[Some(0), Some(3), Some(2), Some(1), Some(4)]).len(), 3);
+    }
+
+    #[test]
+    fn one_child_of_a_reversed_list_stays() {
+        assert_eq!(run(&[Some(2), Some(1), Some(0)]).len(), 1);
+    }
+
+    #[test]
+    fn runs_are_increasing_and_as_long_as_possible() {
+        // Every arrangement of five old children with a new one in between
+        let mut sources = vec![Some(0), Some(1), None, Some(2), Some(3), Some(4)];
+        for _ in 0..720 {
+            let found = run(&sources);
+            assert!(
+                found.windows(2).all(|w| sources[w[0]] < sources[w[1]]),
+                "{sources:?}"
+            );
+            assert!(found.iter().all(|&i| sources[i].is_some()), "{sources:?}");
+            assert_eq!(found.len(), longest_run_len(&sources), "{sources:?}");
+
+            next_permutation(&mut sources);
+        }
+    }
+
+    /// Rearranges the items into the next permutation in lexicographic order, wrapping around
+    fn next_permutation<T: Ord>(items: &mut [T]) {
+        let Some(i) = (1..items.len()).rev().find(|&i| items[i - 1] < items[i]) else {
+            items.reverse();
+            return;
+        };
+        let j = (i..items.len())
+            .rev()
+            .find(|&j| items[i - 1] < items[j])
+            .unwrap();
+        items.swap(i - 1, j);
+        items[i..].reverse();
+    }
+
+    #[test]
+    fn growing_a_list_keeps_the_new_children() {
+        let mut mounted = vec![1];
+        let log = match_numbers(&mut mounted, vec![1, 2, 3]);
+
+        assert_eq!(mounted, vec![1, 2, 3]);
+        assert_eq!(log, ["patch 1 to 1", "create 2", "create 3"]);
+    }
+
+    #[test]
+    fn shrinking_a_list_removes_the_last_children() {
+        let mut mounted = vec![1, 2, 3];
+        let log = match_numbers(&mut mounted, vec![4]);
+
+        assert_eq!(mounted, vec![4]);
+        assert_eq!(log, ["remove 2", "remove 3", "patch 1 to 4"]);
+    }
+
+    #[test]
+    fn a_list_of_the_same_length_is_only_patched() {
+        let mut mounted = vec![1, 2];
+        let log = match_numbers(&mut mounted, vec![3, 4]);
+
+        assert_eq!(mounted, vec![3, 4]);
+        assert_eq!(log, ["patch 1 to 3", "patch 2 to 4"]);
+    }
+}
@@ -1,83 +1,536 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::rc::Rc;
+
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::js_sys::Function;
-use web_sys::{ErrorEvent, MessageEvent, WebSocket};
+use web_sys::js_sys::{Array, Math, Object, Reflect, JSON};
+use web_sys::{CloseEvent, ErrorEvent, MessageEvent, Storage, WebSocket};
+
+/// Delay before the first reconnection attempt, doubled with every failed attempt
+const INITIAL_BACKOFF_MS: f64 = 500.0;
+
+/// Longest delay between two reconnection attempts
+const MAX_BACKOFF_MS: f64 = 30_000.0;
+
+/// Failed reconnection attempts in a row after which the socket gives up
+const MAX_ATTEMPTS: u32 = 10;
+
+/// `sessionStorage` keys that let a reloaded tab carry on as the same client
+const CLIENT_ID_KEY: &str = "galena:client_id";
+const NEXT_MESSAGE_ID_KEY: &str = "galena:next_message_id";
+
+/// Prefix of the `localStorage` key holding a client's outbox
+const OUTBOX_KEY_PREFIX: &str = "galena:outbox:";
+
+/// Reason given when the connection is gone for good after too many failed attempts
+const GAVE_UP_REASON: &str = "Lost the connection to the server, reload the page to try again";
+
+/// What the socket reports as its state changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+    /// Waiting to make the numbered attempt, counting from 1
+    Reconnecting(u32),
+    /// The backend closed the socket on purpose or reconnecting was given up
+    Disconnected(String),
+}
 
 #[wasm_bindgen]
 pub struct ReconnectingWebSocket {
+    inner: Rc<RefCell<Inner>>,
+}
+
+struct Inner {
+    /// The url without the client id, which is added on every connection
     ws_url: String,
+    client_id: String,
     ws: Option<WebSocket>,
+    on_message: Option<Rc<dyn Fn(MessageEvent)>>,
+    on_status: Option<Rc<dyn Fn(ConnectionStatus)>>,
     onmessage_callback: Option<Closure<dyn FnMut(MessageEvent)>>,
+    onopen_callback: Option<Closure<dyn FnMut()>>,
+    onerror_callback: Option<Closure<dyn FnMut(ErrorEvent)>>,
+    onclose_callback: Option<Closure<dyn FnMut(CloseEvent)>>,
+    /// Reconnection attempts since the socket was last open
+    attempts: u32,
+    /// Messages the backend has not acknowledged yet, oldest first
+    outbox: VecDeque<Outgoing>,
+    next_message_id: u64,
+    /// Where the client id and the outbox are kept when they should survive a reload
+    storage: Option<OfflineStorage>,
 }
 
-impl ReconnectingWebSocket {
-    pub fn new(ws_url: String) -> Self {
-        ReconnectingWebSocket {
-            ws_url,
-            ws: None,
-            onmessage_callback: None,
-        }
+/// A message for the backend. The id tells the backend which messages it already got when
+/// the outbox is sent again after a reconnect
+struct Outgoing {
+    message_id: u64,
+    request_id: u64,
+    msg: String,
+}
+
+impl Outgoing {
+    fn frame(&self) -> String {
+        format!(
+            r#"{{"message_id":{},"request_id":{},"msg":{}}}"#,
+            self.message_id, self.request_id, self.msg
+        )
     }
+}
 
-    pub fn connect(&mut self) -> Result<(), JsValue> {
-        let ws = WebSocket::new(&self.ws_url)?;
-        // ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
+/// The client id belongs to the tab, so it goes in `sessionStorage`. The outbox is kept in
+/// `localStorage` under the client id
+struct OfflineStorage {
+    session: Storage,
+    local: Storage,
+}
 
-        let onopen_callback = Closure::wrap(Box::new(move || {
-            web_sys::console::log_1(&"WebSocket opened".into());
-        }) as Box<dyn FnMut()>);
-        ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
-        onopen_callback.forget();
+impl OfflineStorage {
+    fn open() -> Option<Self> {
+        let window = web_sys::window()?;
+
+        Some(OfflineStorage {
+            session: window.session_storage().ok().flatten()?,
+            local: window.local_storage().ok().flatten()?,
+        })
+    }
+
+    fn outbox_key(client_id: &str) -> String {
+        format!("{OUTBOX_KEY_PREFIX}{client_id}")
+    }
+
+    /// Loads the messages a previous page of this tab did not get to send. Their requests
+    /// were made by a page that is gone, so they are sent as plain messages
+    fn restore_outbox(&self, client_id: &str) -> VecDeque<Outgoing> {
+        let Some(json) = self
+            .local
+            .get_item(&Self::outbox_key(client_id))
+            .ok()
+            .flatten()
+        else {
+            return VecDeque::new();
+        };
+        let Ok(messages) = JSON::parse(&json) else {
+            return VecDeque::new();
+        };
+
+        Array::from(&messages)
+            .iter()
+            .filter_map(|entry| {
+                let message_id = Reflect::get(&entry, &"id".into()).ok()?.as_f64()?;
+                let msg = Reflect::get(&entry, &"msg".into()).ok()?.as_string()?;
+
+                Some(Outgoing {
+                    message_id: message_id as u64,
+                    request_id: 0,
+                    msg,
+                })
+            })
+            .collect()
+    }
+
+    fn save(&self, client_id: &str, outbox: &VecDeque<Outgoing>, next_message_id: u64) {
+        _ = self
+            .session
+            .set_item(NEXT_MESSAGE_ID_KEY, &next_message_id.to_string());
 
-        if let Some(onmessage_callback) = &self.onmessage_callback {
-            ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
+        let key = Self::outbox_key(client_id);
+        if outbox.is_empty() {
+            _ = self.local.remove_item(&key);
+            return;
         }
 
-        let onerror_callback = Closure::wrap(Box::new(move |error: ErrorEvent| {
-            web_sys::console::error_1(&error);
-        }) as Box<dyn FnMut(ErrorEvent)>);
-        ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
-        onerror_callback.forget();
+        let messages = Array::new();
+        for message in outbox {
+            let entry = Object::new();
+            _ = Reflect::set(&entry, &"id".into(), &(message.message_id as f64).into());
+            _ = Reflect::set(&entry, &"msg".into(), &message.msg.as_str().into());
+            messages.push(&entry);
+        }
+        let saved = JSON::stringify(&messages)
+            .ok()
+            .and_then(|json| json.as_string())
+            .map(|json| self.local.set_item(&key, &json));
+        if !matches!(saved, Some(Ok(()))) {
+            web_sys::console::warn_1(&"Could not save the outbox".into());
+        }
+    }
+}
 
-        let onclose_callback = Closure::wrap(Box::new(move || {
-            web_sys::console::log_1(&"WebSocket closed".into());
-        }) as Box<dyn FnMut()>);
+impl ReconnectingWebSocket {
+    /// The client id is sent along on every connection, so that the backend sees a
+    /// reconnected socket as the same client. With `offline_storage` the id and any messages
+    /// that were not sent yet survive a reload of the page
+    pub fn new(ws_url: String, offline_storage: bool) -> Self {
+        let storage = offline_storage.then(OfflineStorage::open).flatten();
+        let stored = |key| {
+            storage
+                .as_ref()
+                .and_then(|storage| storage.session.get_item(key).ok().flatten())
+        };
+
+        let client_id = stored(CLIENT_ID_KEY).unwrap_or_else(random_client_id);
+        let mut next_message_id = stored(NEXT_MESSAGE_ID_KEY)
+            .and_then(|id| id.parse().ok())
+            .unwrap_or(1);
+        let mut outbox = VecDeque::new();
+        if let Some(storage) = &storage {
+            _ = storage.session.set_item(CLIENT_ID_KEY, &client_id);
+            outbox = storage.restore_outbox(&client_id);
+        }
+        if let Some(last) = outbox.back() {
+            next_message_id = next_message_id.max(last.message_id + 1);
+        }
 
-        ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
-        onclose_callback.forget();
+        ReconnectingWebSocket {
+            inner: Rc::new(RefCell::new(Inner {
+                ws_url,
+                client_id,
+                ws: None,
+                on_message: None,
+                on_status: None,
+                onmessage_callback: None,
+                onopen_callback: None,
+                onerror_callback: None,
+                onclose_callback: None,
+                attempts: 0,
+                outbox,
+                next_message_id,
+                storage,
+            })),
+        }
+    }
 
-        self.ws = Some(ws);
+    pub fn connect(&self) -> Result<(), JsValue> {
+        connect(&self.inner)?;
+        notify(&self.inner, ConnectionStatus::Connecting);
 
         Ok(())
     }
 
-    pub fn set_onmessage<F>(&mut self, callback: F)
+    pub fn set_onmessage<F>(&self, callback: F)
     where
         F: Fn(MessageEvent) + 'static,
     {
-        let closure = Closure::wrap(Box::new(move |event: MessageEvent| {
-            callback(event);
-        }) as Box<dyn FnMut(MessageEvent)>);
-
-        if let Some(ws) = &self.ws {
-            ws.set_onmessage(Some(closure.as_ref().unchecked_ref()));
-        }
+        self.inner.borrow_mut().on_message = Some(Rc::new(callback));
+    }
 
-        self.onmessage_callback = Some(closure);
+    /// Once set, the banner shown when the connection is gone for good is left to the callback
+    pub fn set_onstatus<F>(&self, callback: F)
+    where
+        F: Fn(ConnectionStatus) + 'static,
+    {
+        self.inner.borrow_mut().on_status = Some(Rc::new(callback));
     }
 
-    pub fn send_message(&self, message: &str) -> Result<(), JsValue> {
-        if let Some(ws) = &self.ws {
-            ws.send_with_str(message)?;
-        }
-        Ok(())
+    /// Queues a message for the backend and sends it right away if the socket is open.
+    /// Otherwise it goes out, in order, once the socket reconnects
+    pub fn send_message(&self, request_id: u64, msg: &str) -> Result<(), JsValue> {
+        let mut inner = self.inner.borrow_mut();
+        let message = Outgoing {
+            message_id: inner.next_message_id,
+            request_id,
+            msg: msg.to_owned(),
+        };
+        inner.next_message_id += 1;
+
+        let sent = match &inner.ws {
+            Some(ws) if ws.ready_state() == WebSocket::OPEN => ws.send_with_str(&message.frame()),
+            _ => Ok(()),
+        };
+        inner.outbox.push_back(message);
+        inner.save();
+
+        sent
     }
 
     pub fn close(&mut self) -> Result<(), JsValue> {
-        if let Some(ws) = &self.ws {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(ws) = inner.ws.take() {
+            // Without its close handler the socket is not reopened
+            ws.set_onclose(None);
             ws.close()?;
         }
-        self.ws = None;
         Ok(())
     }
 }
+
+impl Inner {
+    /// Sends everything the backend has not acknowledged. Messages it did get before the
+    /// connection dropped are recognized by their id and skipped by the backend
+    fn flush_outbox(&self) {
+        let Some(ws) = &self.ws else {
+            return;
+        };
+
+        for message in &self.outbox {
+            if let Err(err) = ws.send_with_str(&message.frame()) {
+                web_sys::console::error_2(&"Could not send queued message".into(), &err);
+                return;
+            }
+        }
+    }
+
+    /// The backend acknowledges messages by the id of the last one it got
+    fn acknowledge(&mut self, message_id: u64) {
+        while self
+            .outbox
+            .front()
+            .is_some_and(|message| message.message_id <= message_id)
+        {
+            self.outbox.pop_front();
+        }
+        self.save();
+    }
+
+    fn save(&self) {
+        if let Some(storage) = &self.storage {
+            storage.save(&self.client_id, &self.outbox, self.next_message_id);
+        }
+    }
+
+    /// Takes on the id the backend gave this client, which differs from the one asked for
+    /// when that one belongs to another session
+    fn adopt_client_id(&mut self, client_id: &str) {
+        if client_id == self.client_id {
+            return;
+        }
+        if let Some(storage) = &self.storage {
+            _ = storage
+                .local
+                .remove_item(&OfflineStorage::outbox_key(&self.client_id));
+            _ = storage.session.set_item(CLIENT_ID_KEY, client_id);
+        }
+        self.client_id = client_id.to_owned();
+        self.save();
+    }
+}
+
+/// Opens a new socket, replacing the handlers of the previous one so that it can no longer
+/// call into closures that are dropped here
+fn connect(inner_rc: &Rc<RefCell<Inner>>) -> Result<(), JsValue> {
+    let mut inner = inner_rc.borrow_mut();
+    if let Some(old) = inner.ws.take() {
+        old.set_onopen(None);
+        old.set_onmessage(None);
+        old.set_onerror(None);
+        old.set_onclose(None);
+    }
+
+    let ws = WebSocket::new(&format!("{}?client_id={}", inner.ws_url, inner.client_id))?;
+    // ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
+
+    let onopen_callback = {
+        let inner_rc = Rc::clone(inner_rc);
+        Closure::wrap(Box::new(move || {
+            web_sys::console::log_1(&"WebSocket opened".into());
+            {
+                let mut inner = inner_rc.borrow_mut();
+                inner.attempts = 0;
+                inner.flush_outbox();
+            }
+            notify(&inner_rc, ConnectionStatus::Connected);
+        }) as Box<dyn FnMut()>)
+    };
+    ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
+
+    let onmessage_callback = {
+        let inner_rc = Rc::clone(inner_rc);
+        Closure::wrap(Box::new(move |event: MessageEvent| {
+            let data = event.data().as_string();
+            if let Some(message_id) = data.as_deref().and_then(parse_ack) {
+                inner_rc.borrow_mut().acknowledge(message_id);
+                return;
+            }
+            if let Some(client_id) = data.as_deref().and_then(parse_client_id) {
+                inner_rc.borrow_mut().adopt_client_id(client_id);
+                return;
+            }
+
+            // Taken out of the cell first, since the handler may send messages of its own
+            let on_message = inner_rc.borrow().on_message.clone();
+            if let Some(on_message) = on_message {
+                on_message(event);
+            }
+        }) as Box<dyn FnMut(MessageEvent)>)
+    };
+    ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
+
+    let onerror_callback = Closure::wrap(Box::new(move |error: ErrorEvent| {
+        web_sys::console::error_1(&error);
+    }) as Box<dyn FnMut(ErrorEvent)>);
+    ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
+
+    let onclose_callback = {
+        let inner_rc = Rc::clone(inner_rc);
+        Closure::wrap(Box::new(move |event: CloseEvent| {
+            web_sys::console::log_1(&"WebSocket closed".into());
+            if closed_by_backend(&event) {
+                let reason = match event.reason() {
+                    reason if reason.is_empty() => "Disconnected by the server".to_owned(),
+                    reason => reason,
+                };
+                disconnected(&inner_rc, reason);
+            } else {
+                schedule_reconnect(&inner_rc);
+            }
+        }) as Box<dyn FnMut(CloseEvent)>)
+    };
+    ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
+
+    inner.ws = Some(ws);
+    inner.onopen_callback = Some(onopen_callback);
+    inner.onmessage_callback = Some(onmessage_callback);
+    inner.onerror_callback = Some(onerror_callback);
+    inner.onclose_callback = Some(onclose_callback);
+
+    Ok(())
+}
+
+/// The backend acknowledges messages with `{"ack":<message id>}`, which cannot be mistaken
+/// for the frame of a `ToFrontendMsg`
+fn parse_ack(data: &str) -> Option<u64> {
+    data.strip_prefix(r#"{"ack":"#)?
+        .strip_suffix('}')?
+        .parse()
+        .ok()
+}
+
+/// The backend tells the client its id with `{"client_id":"<id>"}` as soon as it connects.
+/// Ids are hex, so they need no unescaping
+fn parse_client_id(data: &str) -> Option<&str> {
+    data.strip_prefix(r#"{"client_id":""#)?
+        .strip_suffix(r#""}"#)
+}
+
+/// Reconnects after a jittered, exponentially growing delay. The new socket is opened from
+/// a timeout rather than the close handler, since opening it drops that handler
+fn schedule_reconnect(inner_rc: &Rc<RefCell<Inner>>) {
+    let attempts = {
+        let mut inner = inner_rc.borrow_mut();
+        inner.attempts += 1;
+        inner.attempts
+    };
+    if attempts > MAX_ATTEMPTS {
+        web_sys::console::error_1(&"Giving up reconnecting to the backend".into());
+        disconnected(inner_rc, GAVE_UP_REASON.to_owned());
+        return;
+    }
+    notify(inner_rc, ConnectionStatus::Reconnecting(attempts));
+
+    // Full jitter keeps clients that lost the connection at the same time from all
+    // reconnecting at once
+    let backoff = (INITIAL_BACKOFF_MS * 2f64.powi(attempts as i32 - 1)).min(MAX_BACKOFF_MS);
+    let delay = (backoff * Math::random()) as i32;
+    web_sys::console::log_1(&format!("Reconnecting in {delay}ms, attempt {attempts}").into());
+
+    let inner_rc = Rc::clone(inner_rc);
+    let reconnect = Closure::once_into_js(move || {
+        if let Err(err) = connect(&inner_rc) {
+            web_sys::console::error_2(&"Could not reconnect".into(), &err);
+            schedule_reconnect(&inner_rc);
+        }
+    });
+    if let Some(window) = web_sys::window() {
+        _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+            reconnect.unchecked_ref(),
+            delay,
+        );
+    }
+}
+
+/// Calls the status callback outside of the borrow, since it may send messages
+fn notify(inner_rc: &Rc<RefCell<Inner>>, status: ConnectionStatus) {
+    let on_status = inner_rc.borrow().on_status.clone();
+    if let Some(on_status) = on_status {
+        on_status(status);
+    }
+}
+
+/// Reports that the socket will not reconnect, showing the reason in a banner unless the
+/// app is told about it
+fn disconnected(inner_rc: &Rc<RefCell<Inner>>, reason: String) {
+    if inner_rc.borrow().on_status.is_some() {
+        notify(inner_rc, ConnectionStatus::Disconnected(reason));
+    } else {
+        show_disconnected(&reason);
+    }
+}
+
+/// 16 random bytes as hex, unguessable so that other clients cannot take over the identity
+fn random_client_id() -> String {
+    let mut bytes = [0u8; 16];
+    if let Some(crypto) = web_sys::window().and_then(|window| window.crypto().ok()) {
+        _ = crypto.get_random_values_with_u8_array(&mut bytes);
+    }
+
+    bytes
+        .iter()
+        .fold(String::with_capacity(32), |mut id, byte| {
+            _ = write!(id, "{byte:02x}");
+            id
+        })
+}
+
+/// The backend closes websockets with 1000 or an app defined code when it disconnects a
+/// client on purpose. Anything else, e.g. 1006 for a dropped connection, is not final
+fn closed_by_backend(event: &CloseEvent) -> bool {
+    matches!(event.code(), 1000 | 3000..=4999)
+}
+
+/// Puts the reason the connection is gone on top of the page. The banner lives outside of
+/// the app's root so that rerenders leave it alone
+fn show_disconnected(reason: &str) {
+    let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+        return;
+    };
+    let Some(html) = document.document_element() else {
+        return;
+    };
+    let Ok(banner) = document.create_element("div") else {
+        return;
+    };
+
+    banner.set_text_content(Some(reason));
+    _ = banner.set_attribute("role", "alert");
+    _ = banner.set_attribute("data-galena-disconnected", "");
+    _ = banner.set_attribute(
+        "style",
+        "position: fixed; top: 0; left: 0; right: 0; z-index: 2147483647; padding: 0.75rem; \
+        background: #b91c1c; color: white; font-family: sans-serif; text-align: center;",
+    );
+    _ = html.append_child(&banner);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acks_are_parsed() {
+        assert_eq!(parse_ack(r#"{"ack":0}"#), Some(0));
+        assert_eq!(parse_ack(r#"{"ack":42}"#), Some(42));
+    }
+
+    #[test]
+    fn other_frames_are_not_acks() {
+        for data in [
+            r#"{"ack":-1}"#,
+            r#"{"ack":"1"}"#,
+            r#"{"ack":1,"msg":2}"#,
+            r#"{"reply_to":0,"msg":{"ack":1}}"#,
+            r#"{"client_id":"ab12"}"#,
+            "",
+        ] {
+            assert_eq!(parse_ack(data), None, "{data}");
+        }
+    }
+
+    #[test]
+    fn client_ids_are_parsed() {
+        assert_eq!(parse_client_id(r#"{"client_id":"ab12"}"#), Some("ab12"));
+        assert_eq!(parse_client_id(r#"{"ack":1}"#), None);
+    }
+}
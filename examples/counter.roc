@@ -9,6 +9,7 @@ app [
 ] { galena: platform "../platform/main.roc" }
 
 import galena.Backend as Backend exposing [Backend]
+import galena.Cmd as Cmd exposing [Cmd]
 import galena.Frontend as Frontend exposing [Frontend]
 import galena.Html as Html
 
@@ -43,7 +44,7 @@ frontendApp = Frontend.frontend {
     updateFromBackend: TotalCountUpdate,
 }
 
-frontend_update! : FrontendMsg, FrontendModel => (FrontendModel, Result ToBackendMsg [NoOp])
+frontend_update! : FrontendMsg, FrontendModel => (FrontendModel, Cmd FrontendMsg ToFrontendMsg ToBackendMsg)
 frontend_update! = |msg, model|
     when msg is
         Click ->
@@ -52,7 +53,7 @@ frontend_update! = |msg, model|
                     local_clicks: model.local_clicks + 1,  
                     total_clicks: model.total_clicks 
                 }, 
-                Ok model.local_clicks
+                Cmd.send_to_backend model.local_clicks
             )
 
         TotalCountUpdate backend_clicks -> 
@@ -61,7 +62,7 @@ frontend_update! = |msg, model|
                     local_clicks: model.local_clicks,  
                     total_clicks: backend_clicks
                 }, 
-                Err NoOp
+                Cmd.none
             )
 
 
@@ -129,16 +130,16 @@ view = |{local_clicks, total_clicks}|
 
 backendApp : Backend BackendModel BackendendMsg ToFrontendMsg ToBackendMsg
 backendApp = Backend.backend {
-        init!: { counter: 0 },
+        init!: |_| ({ counter: 0 }, Backend.none),
         update!: |msg, model|
             when msg is
                 UpdateCounter client_id client_counter ->
                     (
                         { counter: model.counter + client_counter },
-                        Ok (client_id, model.counter + client_counter),
+                        Backend.send_to_frontend client_id (model.counter + client_counter),
                     ),
         update_from_frontend: update_from_frontend,
     }
 
-update_from_frontend : Str, Str, ToBackendMsg -> BackendendMsg
-update_from_frontend = |client_id, _, client_counter| UpdateCounter client_id client_counter
+update_from_frontend : Backend.Client, ToBackendMsg -> BackendendMsg
+update_from_frontend = |{ client_id }, client_counter| UpdateCounter client_id client_counter
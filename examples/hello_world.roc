@@ -9,6 +9,7 @@ app [
 ] { galena: platform "../platform/main.roc" }
 
 import galena.Backend as Backend exposing [Backend]
+import galena.Cmd as Cmd exposing [Cmd]
 import galena.Frontend as Frontend exposing [Frontend]
 import galena.View as View
 
@@ -37,18 +38,18 @@ frontendApp = Frontend.frontend {
     updateFromBackend: |_| NoOp,
 }
 
-frontend_update! : FrontendMsg, FrontendModel => (FrontendModel, Result ToBackendMsg [NoOp])
+frontend_update! : FrontendMsg, FrontendModel => (FrontendModel, Cmd FrontendMsg ToFrontendMsg ToBackendMsg)
 frontend_update! = |msg, model|
     when msg is
         Decrement ->
             incr = model.counter - 1
-            ({ counter: incr }, Err NoOp)
+            ({ counter: incr }, Cmd.none)
 
         Increment ->
             incr = model.counter + 1
-            ({ counter: incr }, Err NoOp)
+            ({ counter: incr }, Cmd.none)
 
-        NoOp -> (model, Err NoOp)
+        NoOp -> (model, Cmd.none)
 
 view : FrontendModel -> View.View FrontendMsg
 view = |model|
@@ -76,17 +77,17 @@ view = |model|
 
 backendApp : Backend BackendModel BackendendMsg ToFrontendMsg ToBackendMsg
 backendApp = Backend.backend {
-    init!: { counter: 0 },
+    init!: |_| ({ counter: 0 }, Backend.none),
     update!: |msg, model|
         when msg is
             UpdateCounter client_id client_counter ->
                 (
                     { counter: model.counter + client_counter },
-                    Ok (client_id, model.counter + client_counter),
+                    Backend.send_to_frontend client_id (model.counter + client_counter),
                 ),
     update_from_frontend: update_from_frontend,
 }
 
-update_from_frontend : Str, Str, ToBackendMsg -> BackendendMsg
-update_from_frontend = |client_id, _, client_counter| UpdateCounter client_id client_counter
+update_from_frontend : Backend.Client, ToBackendMsg -> BackendendMsg
+update_from_frontend = |{ client_id }, client_counter| UpdateCounter client_id client_counter
 
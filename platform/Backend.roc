@@ -1,7 +1,58 @@
-module [Backend, backend]
+module [Backend, Cmd, Request, Startup, Client, backend, with_routes, with_requests, with_database, with_jobs, with_presence, none, batch, send_to_frontend, respond, subscribe, unsubscribe, publish, disconnect, disconnect_session]
 
-import Internal.Backend exposing [BackendInternal, backend_]
+import Internal.Backend exposing [BackendInternal, backend_, with_routes_, with_requests_, with_database_, with_jobs_, with_presence_]
+import Internal.Client
+import Internal.BackendCmd exposing [BackendCmd, FrontendRequest, none_, batch_, send_to_frontend_, respond_, subscribe_, unsubscribe_, publish_, disconnect_, disconnect_session_]
 
 Backend model msg toFrontendMsg toBackendMsg : BackendInternal model msg toFrontendMsg toBackendMsg
 
+Cmd msg toFrontendMsg : BackendCmd msg toFrontendMsg
+
+Request : FrontendRequest
+
+Startup : Internal.Backend.Startup
+
+Client : Internal.Client.Client
+
 backend = backend_
+
+# Serves the given routes from the backend alongside the frontend. Requests that do not
+# match any route fall through to the frontend's static files
+with_routes = with_routes_
+
+# Turns messages the frontend sent with `Cmd.request` into backend messages. The request
+# is kept around until `update!` answers it with `respond`
+with_requests = with_requests_
+
+# Gives the app an sqlite database for `Db.query` and `Db.transaction`. The file is created
+# on startup inside the host's data directory
+with_database = with_database_
+
+# Registers the jobs that `Job.enqueue` can queue by name
+with_jobs = with_jobs_
+
+# Turns the list of connected clients into a message whenever a client connects or
+# disconnects, so the app can tell who is online
+with_presence = with_presence_
+
+none = none_
+batch = batch_
+send_to_frontend = send_to_frontend_
+respond = respond_
+
+# Adds a client to a topic. Clients leave all their topics when they disconnect
+subscribe = subscribe_
+
+unsubscribe = unsubscribe_
+
+# Sends a `ToFrontendMsg` to every client subscribed to the topic. Subscription changes made
+# by the same command take effect before the message goes out
+publish = publish_
+
+# Closes a client's websocket with a close code and a reason. The frontend shows the reason
+# instead of reconnecting. Codes should be 1000 or between 3000 and 4999, anything else is
+# sent as 4000, and reasons longer than 123 bytes are cut short
+disconnect = disconnect_
+
+# Like `disconnect` for every websocket of a session, e.g. all tabs of a user who logged out
+disconnect_session = disconnect_session_
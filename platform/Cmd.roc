@@ -1,5 +1,65 @@
-module [Cmd, none]
+module [
+    Cmd,
+    RequestError,
+    DomError,
+    none,
+    batch,
+    send_to_backend,
+    request,
+    request_with_timeout,
+    delay,
+    focus,
+    blur,
+    scroll_into_view,
+    log,
+]
 
-Cmd msg := {}
+import Internal.FrontendCmd exposing [
+    FrontendCmd,
+    none_,
+    batch_,
+    send_to_backend_,
+    request_,
+    delay_,
+    focus_,
+    blur_,
+    scroll_into_view_,
+    log_,
+]
 
-none = @Cmd {}
+# Work the frontend's `update!` asks the platform to carry out
+Cmd msg toFrontendMsg toBackendMsg : FrontendCmd msg toFrontendMsg toBackendMsg
+
+RequestError : Internal.FrontendCmd.RequestError
+DomError : Internal.FrontendCmd.DomError
+
+none = none_
+batch = batch_
+send_to_backend = send_to_backend_
+
+# Sends `msg` to the backend and turns the backend's `Backend.respond` to it into a
+# frontend message. Gives up with `Err Timeout` after 30 seconds
+request : toBackendMsg, (Result toFrontendMsg RequestError -> msg) -> Cmd msg toFrontendMsg toBackendMsg
+request = |msg, on_reply| request_ msg (TimeoutMilliseconds 30_000) on_reply
+
+request_with_timeout : toBackendMsg, [TimeoutMilliseconds U64, NoTimeout], (Result toFrontendMsg RequestError -> msg) -> Cmd msg toFrontendMsg toBackendMsg
+request_with_timeout = request_
+
+# Gives `msg` to `update!` after waiting the given number of milliseconds
+delay : U64, msg -> Cmd msg toFrontendMsg toBackendMsg
+delay = delay_
+
+# Focus, blur or scroll into view the element with the given id. They run once the update
+# has been rendered, so they can target elements the update adds to the page
+focus : Str, (Result {} DomError -> msg) -> Cmd msg toFrontendMsg toBackendMsg
+focus = focus_
+
+blur : Str, (Result {} DomError -> msg) -> Cmd msg toFrontendMsg toBackendMsg
+blur = blur_
+
+scroll_into_view : Str, (Result {} DomError -> msg) -> Cmd msg toFrontendMsg toBackendMsg
+scroll_into_view = scroll_into_view_
+
+# Writes a line to the browser console
+log : Str -> Cmd msg toFrontendMsg toBackendMsg
+log = log_
@@ -0,0 +1,32 @@
+module [
+    Value,
+    Statement,
+    QueryResult,
+    Error,
+    query,
+    transaction,
+]
+
+import Internal.BackendCmd exposing [BackendCmd, db_request_]
+import Internal.Db
+
+Value : Internal.Db.Value
+Statement : Internal.Db.Statement
+QueryResult : Internal.Db.QueryResult
+Error : Internal.Db.Error
+
+# Runs a single statement against the app's database, binding `params` to its `?`
+# placeholders in order
+query : Str, List Value, (Result QueryResult Error -> msg) -> BackendCmd msg to_frontend_msg
+query = |sql, params, on_result|
+    db_request_
+        [{ sql, params }]
+        (|result|
+            result
+            |> Result.try (|results| List.first results |> Result.map_err (|_| SqlError "Statement returned no result"))
+            |> on_result)
+
+# Runs the statements in order inside one transaction. If any of them fails the whole
+# transaction is rolled back and the error is returned
+transaction : List Statement, (Result (List QueryResult) Error -> msg) -> BackendCmd msg to_frontend_msg
+transaction = db_request_
@@ -0,0 +1,10 @@
+module [var!]
+
+import Host
+
+# Reads a variable from the backend's environment. Variables set on the process take
+# precedence over the ones in the host's secrets file. Always fails on the frontend
+var! : Str => Result Str [VarNotFound]
+var! = |name|
+    Host.env_var! name
+    |> Result.map_err (|{}| VarNotFound)
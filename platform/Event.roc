@@ -0,0 +1,35 @@
+module [
+    Decoder,
+    succeed,
+    bool,
+    number,
+    string,
+    json,
+    maybe,
+    map,
+    map2,
+    map3,
+]
+
+import Internal.Event exposing [succeed_, bool_, number_, string_, json_, maybe_, map_, map2_, map3_]
+
+# Turns the properties of an event into a message for `Html.on`. Properties are given as
+# the path to them from the event, e.g. `["target", "value"]`, and only the ones a decoder
+# names are read. Events where one of them is missing or of another kind are ignored
+Decoder a : Internal.Event.Decoder a
+
+succeed = succeed_
+bool = bool_
+number = number_
+string = string_
+
+# The property as json text, for objects and arrays like `detail` or `touches`
+json = json_
+
+# Lets the properties of the decoder be missing or of another kind instead of ignoring
+# the event
+maybe = maybe_
+
+map = map_
+map2 = map2_
+map3 = map3_
@@ -1,8 +1,18 @@
-module [frontend, Frontend]
+module [frontend, Frontend, ConnectionStatus, with_offline_storage, with_connection_change]
 
-import Internal.Frontend exposing [InternalFrontend, frontend_]
+import Internal.Frontend exposing [InternalFrontend, frontend_, with_offline_storage_, with_connection_change_]
 
 Frontend model msg toFrontendMsg toBackendMsg : InternalFrontend model msg toFrontendMsg toBackendMsg
 
+ConnectionStatus : Internal.Frontend.ConnectionStatus
+
 frontend = frontend_
 
+# Keeps messages that are waiting for the websocket to reconnect in `localStorage`, so they
+# are still sent when the page is reloaded before the connection comes back
+with_offline_storage = with_offline_storage_
+
+# Turns changes to the state of the connection to the backend into a message, so the app can
+# tell the user it is offline or hold back actions until it is back
+with_connection_change = with_connection_change_
+
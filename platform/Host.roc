@@ -0,0 +1,7 @@
+hosted [log!, env_var!]
+
+# `level` is 0 for debug, 1 for info, 2 for warn and 3 for error. `fields` are key/value
+# pairs that are kept apart from the message
+log! : U8, Str, List (Str, Str) => {}
+
+env_var! : Str => Result Str {}
@@ -1,7 +1,11 @@
 module [
     Html,
     Attr,
+    EventResult,
     map,
+    keyed,
+    lazy,
+    lazy_keyed,
     
     # HTML elements
     text,
@@ -87,11 +91,19 @@ module [
     on_mouseleave,
     on_load,
     on_scroll,
+    prevent_default_on,
+    stop_propagation_on,
+    custom_on,
+    on,
 ]
 
+import json.Json
+
 import Internal.Html exposing [
     InternalHtml,
     map_,
+    keyed_,
+    lazy_keyed_,
     text_,
     div_,
     p_,
@@ -176,15 +188,43 @@ import Internal.Attr exposing [
     on_mouseleave_,
     on_load_,
     on_scroll_,
+    prevent_default_on_,
+    stop_propagation_on_,
+    custom_on_,
+    on_,
     attribute_,
 ]
 
 # Type aliases
 Html msg : InternalHtml msg
 Attr msg : InternalAttr msg
+EventResult msg : Internal.Attr.EventResult msg
 
 map = map_
 
+# An element whose children each come with a key, e.g. the id of the item they show. When
+# the list changes, children are matched up by key, so their DOM nodes are moved rather
+# than rewritten. Keys should be unique among the children
+keyed = keyed_
+
+# `lazy view arg` is `view arg`, except that the host skips calling `view` and comparing
+# what it returns with the page while `arg` is structurally equal to the one in the last
+# render. `arg` is compared by its json encoding, which is built on every render, so for
+# large arguments `lazy_keyed` with a cheaper key can be faster
+lazy : (a -> Html msg), a -> Html msg where a implements Encoding
+lazy = |view, arg|
+    key =
+        Encode.to_bytes arg Json.utf8
+        |> Str.from_utf8
+        |> Result.with_default ""
+    lazy_keyed_ key view arg
+
+# `lazy_keyed key view arg` is `view arg`, except that the host skips calling `view` and
+# comparing what it returns with the page while `key` is the same as in the last render.
+# The key stands for `arg`, e.g. the version of the data it holds, so it has to change
+# whenever `arg` does
+lazy_keyed = lazy_keyed_
+
 # HTML elements
 text = text_
 div = div_
@@ -291,4 +331,14 @@ on_mouseleave = on_mouseleave_
 on_load = on_load_
 on_scroll = on_scroll_
 
+# Like the handlers above, but for any event type and with a say in what else happens to
+# the event. `on_submit` already prevents the default, so forms do not reload the page
+prevent_default_on = prevent_default_on_
+stop_propagation_on = stop_propagation_on_
+custom_on = custom_on_
+
+# Handler for the properties of the event an `Event.Decoder` names, e.g.
+# `on "wheel" (Event.map (Event.number ["deltaY"]) Scrolled)`
+on = on_
+
 attribute = attribute_
@@ -0,0 +1,39 @@
+module [
+    Method,
+    Header,
+    Request,
+    Response,
+    Error,
+    default_request,
+    header,
+    request,
+]
+
+import Internal.Http
+import Internal.BackendCmd exposing [BackendCmd, http_request_]
+
+Method : Internal.Http.Method
+Header : Internal.Http.Header
+Request : Internal.Http.Request
+Response : Internal.Http.Response
+Error : Internal.Http.Error
+
+# A GET request with no headers or body that times out after 30 seconds
+default_request : Request
+default_request = {
+    method: Get,
+    url: "",
+    headers: [],
+    body: [],
+    timeout: TimeoutMilliseconds 30_000,
+}
+
+header : Str, Str -> Header
+header = |name, value| { name, value }
+
+# Sends the request from the backend host. Once a response is received, or the request
+# fails, the result is passed to `on_response` and the message it produces is given to
+# the backend's `update!`. Requests with `NoTimeout` still time out after five minutes,
+# and bodies larger than 10 MiB fail with a `NetworkError`
+request : Request, (Result Response Error -> msg) -> BackendCmd msg to_frontend_msg
+request = http_request_
@@ -1,6 +1,7 @@
 module [
     InternalAttr,
     InternalEvent,
+    EventResult,
     map,
 
     # Attribute constructors
@@ -44,8 +45,14 @@ module [
     on_mouseleave_,
     on_load_,
     on_scroll_,
+    prevent_default_on_,
+    stop_propagation_on_,
+    custom_on_,
+    on_,
 ]
 
+import Internal.Event exposing [Decoder, EventProperty, HostEventValue]
+
 InternalAttr msg := [
     # Standard attributes
     Id Str,
@@ -75,7 +82,8 @@ InternalAttr msg := [
     DataAttribute Str Str, # data-key value
 
     # Events
-    OnEvent Str (InternalEvent -> msg),
+    # The properties are read from the event into `values` before calling the handler
+    OnEvent Str (List EventProperty) (InternalEvent -> EventResult msg),
 
     # Custom attribute fallback
     Attribute Str Str,
@@ -84,13 +92,17 @@ InternalAttr msg := [
 map : InternalAttr oldMsg, (oldMsg -> newMsg) -> InternalAttr newMsg
 map = |attr, mapper|
     when attr is
-        @InternalAttr (OnEvent eventType handler) ->
+        @InternalAttr (OnEvent eventType properties handler) ->
             # Transform the event handler
             newHandler = |event|
                 originalResult = handler event
-                mapper originalResult
+                {
+                    message: mapper originalResult.message,
+                    prevent_default: originalResult.prevent_default,
+                    stop_propagation: originalResult.stop_propagation,
+                }
 
-            @InternalAttr (OnEvent eventType newHandler)
+            @InternalAttr (OnEvent eventType properties newHandler)
 
         # All other attributes don't contain messages, so they can be safely "cast"
         @InternalAttr (Id value) -> @InternalAttr (Id value)
@@ -141,6 +153,15 @@ InternalEvent : {
     metaKey : Bool,
     preventDefault : Bool,
     stopPropagation : Bool,
+    values : List HostEventValue, # The properties asked for by the handler's attribute
+}
+
+# What a handler makes of an event. `stop_propagation` keeps the handlers of the elements
+# around the one handling the event from seeing it
+EventResult msg : {
+    message : msg,
+    prevent_default : Bool,
+    stop_propagation : Bool,
 }
 
 # Standard attribute constructors
@@ -210,59 +231,92 @@ data_ : Str, Str -> InternalAttr msg
 data_ = |key, value| @InternalAttr(DataAttribute key value)
 
 # Event handler constructors
+handle_ : Str, (InternalEvent -> msg) -> InternalAttr msg
+handle_ = |event_type, handler|
+    custom_on_ event_type |event| { message: handler event, prevent_default: Bool.false, stop_propagation: Bool.false }
+
 on_click_ : (InternalEvent -> msg) -> InternalAttr msg
-on_click_ = |handler| @InternalAttr(OnEvent "click" handler)
+on_click_ = |handler| handle_ "click" handler
 
 on_input_ : (InternalEvent -> msg) -> InternalAttr msg
-on_input_ = |handler| @InternalAttr(OnEvent "input" handler)
+on_input_ = |handler| handle_ "input" handler
 
 on_change_ : (InternalEvent -> msg) -> InternalAttr msg
-on_change_ = |handler| @InternalAttr(OnEvent "change" handler)
+on_change_ = |handler| handle_ "change" handler
 
+# The browser would otherwise post the form and load the page it gets back
 on_submit_ : (InternalEvent -> msg) -> InternalAttr msg
-on_submit_ = |handler| @InternalAttr(OnEvent "submit" handler)
+on_submit_ = |handler|
+    custom_on_ "submit" |event| { message: handler event, prevent_default: Bool.true, stop_propagation: Bool.false }
 
 on_focus_ : (InternalEvent -> msg) -> InternalAttr msg
-on_focus_ = |handler| @InternalAttr(OnEvent "focus" handler)
+on_focus_ = |handler| handle_ "focus" handler
 
 on_blur_ : (InternalEvent -> msg) -> InternalAttr msg
-on_blur_ = |handler| @InternalAttr(OnEvent "blur" handler)
+on_blur_ = |handler| handle_ "blur" handler
 
 # Keyboard events
 on_keydown_ : (InternalEvent -> msg) -> InternalAttr msg
-on_keydown_ = |handler| @InternalAttr(OnEvent "keydown" handler)
+on_keydown_ = |handler| handle_ "keydown" handler
 
 on_keyup_ : (InternalEvent -> msg) -> InternalAttr msg
-on_keyup_ = |handler| @InternalAttr(OnEvent "keyup" handler)
+on_keyup_ = |handler| handle_ "keyup" handler
 
 on_keypress_ : (InternalEvent -> msg) -> InternalAttr msg
-on_keypress_ = |handler| @InternalAttr(OnEvent "keypress" handler)
+on_keypress_ = |handler| handle_ "keypress" handler
 
 # Mouse events
 on_mousedown_ : (InternalEvent -> msg) -> InternalAttr msg
-on_mousedown_ = |handler| @InternalAttr(OnEvent "mousedown" handler)
+on_mousedown_ = |handler| handle_ "mousedown" handler
 
 on_mouseup_ : (InternalEvent -> msg) -> InternalAttr msg
-on_mouseup_ = |handler| @InternalAttr(OnEvent "mouseup" handler)
+on_mouseup_ = |handler| handle_ "mouseup" handler
 
 on_mouseover_ : (InternalEvent -> msg) -> InternalAttr msg
-on_mouseover_ = |handler| @InternalAttr(OnEvent "mouseover" handler)
+on_mouseover_ = |handler| handle_ "mouseover" handler
 
 on_mouseout_ : (InternalEvent -> msg) -> InternalAttr msg
-on_mouseout_ = |handler| @InternalAttr(OnEvent "mouseout" handler)
+on_mouseout_ = |handler| handle_ "mouseout" handler
 
 on_mouseenter_ : (InternalEvent -> msg) -> InternalAttr msg
-on_mouseenter_ = |handler| @InternalAttr(OnEvent "mouseenter" handler)
+on_mouseenter_ = |handler| handle_ "mouseenter" handler
 
 on_mouseleave_ : (InternalEvent -> msg) -> InternalAttr msg
-on_mouseleave_ = |handler| @InternalAttr(OnEvent "mouseleave" handler)
+on_mouseleave_ = |handler| handle_ "mouseleave" handler
 
 # Other events
 on_load_ : (InternalEvent -> msg) -> InternalAttr msg
-on_load_ = |handler| @InternalAttr(OnEvent "load" handler)
+on_load_ = |handler| handle_ "load" handler
 
 on_scroll_ : (InternalEvent -> msg) -> InternalAttr msg
-on_scroll_ = |handler| @InternalAttr(OnEvent "scroll" handler)
+on_scroll_ = |handler| handle_ "scroll" handler
+
+# Handlers that decide for every event whether its default action and its propagation
+# to the handlers of enclosing elements are stopped
+prevent_default_on_ : Str, (InternalEvent -> (msg, Bool)) -> InternalAttr msg
+prevent_default_on_ = |event_type, handler|
+    custom_on_ event_type |event|
+        (message, prevent_default) = handler event
+        { message, prevent_default, stop_propagation: Bool.false }
+
+stop_propagation_on_ : Str, (InternalEvent -> (msg, Bool)) -> InternalAttr msg
+stop_propagation_on_ = |event_type, handler|
+    custom_on_ event_type |event|
+        (message, stop_propagation) = handler event
+        { message, prevent_default: Bool.false, stop_propagation }
+
+custom_on_ : Str, (InternalEvent -> EventResult msg) -> InternalAttr msg
+custom_on_ = |event_type, handler| @InternalAttr(OnEvent event_type [] handler)
+
+# Handler for any property of the event, e.g. `CustomEvent.detail` or the deltas of a wheel
+on_ : Str, Decoder msg -> InternalAttr msg
+on_ = |event_type, decoder|
+    @InternalAttr(
+        OnEvent
+            event_type
+            (Internal.Event.properties decoder)
+            |event| { message: Internal.Event.decode decoder event.values, prevent_default: Bool.false, stop_propagation: Bool.false },
+    )
 
 attribute_ : Str, Str -> InternalAttr msg
 attribute_ = |attr_name, attr_value| @InternalAttr(Attribute(attr_name, attr_value))
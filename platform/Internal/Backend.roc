@@ -1,19 +1,53 @@
-module [BackendInternal, backend_, inner]
+module [BackendInternal, Startup, HostStartup, backend_, from_host_startup, with_routes_, with_requests_, with_database_, with_jobs_, with_presence_, inner]
 
 import json.Json
+import Internal.BackendCmd exposing [BackendCmd, FrontendRequest]
+import Internal.Route exposing [Route]
+import Internal.Job exposing [Job]
+import Internal.Client exposing [Client]
 
 BackendInternal model msg to_frontend_msg to_backend_msg := {
-    init! : model,
-    update! : msg, model => (model, Result (Str, to_frontend_msg) [NoOp]),
-    update_from_frontend : Str, Str, to_backend_msg -> msg,
+    init! : Startup => (model, BackendCmd msg to_frontend_msg),
+    update! : msg, model => (model, BackendCmd msg to_frontend_msg),
+    update_from_frontend : Client, to_backend_msg -> msg,
+    update_from_request : FrontendRequest, to_backend_msg -> msg,
+    routes : List (Route model msg),
+    # File name of the app's sqlite database inside the data directory, empty for none
+    database : Str,
+    jobs : List (Job msg),
+    on_presence : Result (List Client -> msg) [NoPresence],
     encode_to_frontend_msg : to_frontend_msg -> List U8,
-    decode_to_backend_msg : List U8 -> to_backend_msg,
+    decode_to_backend_msg : List U8 -> Result to_backend_msg [InvalidToBackendMsg],
+    decode_from_frontend : List U8 -> Result (FromFrontend to_backend_msg) [InvalidToBackendMsg],
+}
+
+# What the frontend host sends over the websocket. `request_id` is 0 unless the message
+# was sent with `Cmd.request`
+FromFrontend to_backend_msg : { request_id : U64, msg : to_backend_msg }
+
+# What the backend's `init!` is told about the host it is starting in. `now_ms` is the
+# current time in milliseconds since the unix epoch
+Startup : {
+    config : { address : Str, data_dir : Str },
+    build_version : Str,
+    # The host does not snapshot models yet, so this is always false for now
+    restoring : Bool,
+    now_ms : U64,
+}
+
+# The startup record as handed over by the host
+HostStartup : {
+    address : Str,
+    data_dir : Str,
+    build_version : Str,
+    restoring : Bool,
+    now_ms : U64,
 }
 
 InternalBackendAppSpec model msg to_frontend_msg to_backend_msg : {
-    init! : model,
-    update! : msg, model => (model, Result (Str, to_frontend_msg) [NoOp]),
-    update_from_frontend : Str, Str, to_backend_msg -> msg,
+    init! : Startup => (model, BackendCmd msg to_frontend_msg),
+    update! : msg, model => (model, BackendCmd msg to_frontend_msg),
+    update_from_frontend : Client, to_backend_msg -> msg,
 }
 
 backend_ : InternalBackendAppSpec model msg to_frontend_msg to_backend_msg -> BackendInternal model msg to_frontend_msg to_backend_msg where to_backend_msg implements Decoding, to_frontend_msg implements Encoding
@@ -21,17 +55,46 @@ backend_ = |backend_config|
     @BackendInternal {
         init!: backend_config.init!,
         update!: backend_config.update!,
-        update_from_frontend: |client_id, session_id, to_backend_msg|
-            backend_config.update_from_frontend client_id session_id to_backend_msg,
+        update_from_frontend: backend_config.update_from_frontend,
+        # Requests are plain messages until the app opts in with `Backend.with_requests`, in
+        # which case the frontend never gets a reply and sees a timeout
+        update_from_request: |{ client }, to_backend_msg|
+            backend_config.update_from_frontend client to_backend_msg,
+        routes: [],
+        database: "",
+        jobs: [],
+        on_presence: Err NoPresence,
         encode_to_frontend_msg: |to_frontend_msg| Encode.to_bytes to_frontend_msg Json.utf8,
         decode_to_backend_msg: |msg_bytes|
-            when Decode.from_bytes msg_bytes Json.utf8 is
-                Ok msg ->
-                    msg
-
-                Err _ ->
-                    crash "Unable to decode toBackendMsg this is a platform bug",
+            Decode.from_bytes msg_bytes Json.utf8
+            |> Result.map_err (|_| InvalidToBackendMsg),
+        decode_from_frontend: |msg_bytes|
+            Decode.from_bytes msg_bytes Json.utf8
+            |> Result.map_err (|_| InvalidToBackendMsg),
     }
 
-inner = |@BackendInternal(i)| i
+with_routes_ : BackendInternal model msg to_frontend_msg to_backend_msg, List (Route model msg) -> BackendInternal model msg to_frontend_msg to_backend_msg
+with_routes_ = |@BackendInternal backend, routes|
+    @BackendInternal { backend & routes: List.concat backend.routes routes }
 
+with_requests_ : BackendInternal model msg to_frontend_msg to_backend_msg, (FrontendRequest, to_backend_msg -> msg) -> BackendInternal model msg to_frontend_msg to_backend_msg
+with_requests_ = |@BackendInternal backend, update_from_request|
+    @BackendInternal { backend & update_from_request }
+
+with_database_ : BackendInternal model msg to_frontend_msg to_backend_msg, Str -> BackendInternal model msg to_frontend_msg to_backend_msg
+with_database_ = |@BackendInternal backend, database|
+    @BackendInternal { backend & database }
+
+with_jobs_ : BackendInternal model msg to_frontend_msg to_backend_msg, List (Job msg) -> BackendInternal model msg to_frontend_msg to_backend_msg
+with_jobs_ = |@BackendInternal backend, jobs|
+    @BackendInternal { backend & jobs: List.concat backend.jobs jobs }
+
+with_presence_ : BackendInternal model msg to_frontend_msg to_backend_msg, (List Client -> msg) -> BackendInternal model msg to_frontend_msg to_backend_msg
+with_presence_ = |@BackendInternal backend, on_presence|
+    @BackendInternal { backend & on_presence: Ok on_presence }
+
+from_host_startup : HostStartup -> Startup
+from_host_startup = |{ address, data_dir, build_version, restoring, now_ms }|
+    { config: { address, data_dir }, build_version, restoring, now_ms }
+
+inner = |@BackendInternal(i)| i
@@ -0,0 +1,200 @@
+module [
+    BackendCmd,
+    Pending,
+    HostEffects,
+    FrontendRequest,
+    none_,
+    batch_,
+    send_to_frontend_,
+    respond_,
+    http_request_,
+    db_request_,
+    enqueue_job_,
+    subscribe_,
+    unsubscribe_,
+    publish_,
+    disconnect_,
+    disconnect_session_,
+    empty_pending,
+    no_effects,
+    run,
+    take_http_handler,
+    take_db_handler,
+]
+
+import Internal.Http exposing [Request, Response, Error, HostRequest]
+import Internal.Db
+import Internal.Job
+import Internal.Client exposing [Client]
+
+BackendCmd msg to_frontend_msg := [
+    None,
+    Batch (List (BackendCmd msg to_frontend_msg)),
+    SendToFrontend Str to_frontend_msg,
+    Respond FrontendRequest to_frontend_msg,
+    HttpRequest Request (Result Response Error -> msg),
+    DbRequest (List Internal.Db.Statement) (Result (List Internal.Db.QueryResult) Internal.Db.Error -> msg),
+    EnqueueJob Internal.Job.HostJob,
+    Subscribe Str Str,
+    Unsubscribe Str Str,
+    Publish Str to_frontend_msg,
+    Disconnect [Client Str, Session Str] U16 Str,
+]
+
+# Handlers for commands whose results come back from the host at a later point. These
+# are stored alongside the backend model and looked up by the id the host echoes back
+Pending msg : {
+    next_id : U64,
+    http : Dict U64 (Result Response Error -> msg),
+    db : Dict U64 (Result (List Internal.Db.QueryResult) Internal.Db.Error -> msg),
+}
+
+# A request made by a frontend with `Cmd.request`, answered with `Backend.respond`
+FrontendRequest : {
+    client : Client,
+    id : U64,
+}
+
+# Everything the host has to carry out after an update. `reply_to` is the id of the
+# request a message answers or 0 when it is not a reply. Changes to `topics` subscribe a
+# client when `subscribe` is true and unsubscribe it otherwise, and are applied before
+# anything in `published` goes out. `disconnects` close the websocket of the client with
+# id `id`, or every websocket of the session with that id when `session` is true
+HostEffects : {
+    to_frontend : List { client_id : Str, message : Str, reply_to : U64 },
+    http_requests : List HostRequest,
+    db_requests : List Internal.Db.HostRequest,
+    jobs : List Internal.Job.HostJob,
+    topics : List { client_id : Str, topic : Str, subscribe : Bool },
+    published : List { topic : Str, message : Str },
+    disconnects : List { id : Str, session : Bool, code : U16, reason : Str },
+}
+
+none_ : BackendCmd msg to_frontend_msg
+none_ = @BackendCmd None
+
+batch_ : List (BackendCmd msg to_frontend_msg) -> BackendCmd msg to_frontend_msg
+batch_ = |cmds| @BackendCmd (Batch cmds)
+
+send_to_frontend_ : Str, to_frontend_msg -> BackendCmd msg to_frontend_msg
+send_to_frontend_ = |client_id, msg| @BackendCmd (SendToFrontend client_id msg)
+
+respond_ : FrontendRequest, to_frontend_msg -> BackendCmd msg to_frontend_msg
+respond_ = |request, msg| @BackendCmd (Respond request msg)
+
+http_request_ : Request, (Result Response Error -> msg) -> BackendCmd msg to_frontend_msg
+http_request_ = |request, on_response| @BackendCmd (HttpRequest request on_response)
+
+db_request_ : List Internal.Db.Statement, (Result (List Internal.Db.QueryResult) Internal.Db.Error -> msg) -> BackendCmd msg to_frontend_msg
+db_request_ = |statements, on_result| @BackendCmd (DbRequest statements on_result)
+
+enqueue_job_ : Internal.Job.HostJob -> BackendCmd msg to_frontend_msg
+enqueue_job_ = |job| @BackendCmd (EnqueueJob job)
+
+subscribe_ : Str, Str -> BackendCmd msg to_frontend_msg
+subscribe_ = |client_id, topic| @BackendCmd (Subscribe client_id topic)
+
+unsubscribe_ : Str, Str -> BackendCmd msg to_frontend_msg
+unsubscribe_ = |client_id, topic| @BackendCmd (Unsubscribe client_id topic)
+
+publish_ : Str, to_frontend_msg -> BackendCmd msg to_frontend_msg
+publish_ = |topic, msg| @BackendCmd (Publish topic msg)
+
+disconnect_ : Str, U16, Str -> BackendCmd msg to_frontend_msg
+disconnect_ = |client_id, code, reason| @BackendCmd (Disconnect (Client client_id) code reason)
+
+disconnect_session_ : Str, U16, Str -> BackendCmd msg to_frontend_msg
+disconnect_session_ = |session_id, code, reason| @BackendCmd (Disconnect (Session session_id) code reason)
+
+empty_pending : Pending msg
+empty_pending = { next_id: 0, http: Dict.empty {}, db: Dict.empty {} }
+
+no_effects : HostEffects
+no_effects = { to_frontend: [], http_requests: [], db_requests: [], jobs: [], topics: [], published: [], disconnects: [] }
+
+# Flattens a command into the effects the host should perform, registering handlers
+# for any results that will be delivered later
+run : BackendCmd msg to_frontend_msg, Pending msg, (to_frontend_msg -> List U8) -> (Pending msg, HostEffects)
+run = |cmd, pending, encode_to_frontend_msg|
+    run_help cmd (pending, no_effects) encode_to_frontend_msg
+
+run_help = |@BackendCmd cmd, (pending, effects), encode_to_frontend_msg|
+    when cmd is
+        None ->
+            (pending, effects)
+
+        Batch cmds ->
+            List.walk
+                cmds
+                (pending, effects)
+                (|acc, c| run_help c acc encode_to_frontend_msg)
+
+        SendToFrontend client_id msg ->
+            message = Str.from_utf8_lossy (encode_to_frontend_msg msg)
+            to_frontend = List.append effects.to_frontend { client_id, message, reply_to: 0 }
+
+            (pending, { effects & to_frontend })
+
+        Respond { client, id } msg ->
+            message = Str.from_utf8_lossy (encode_to_frontend_msg msg)
+            to_frontend = List.append effects.to_frontend { client_id: client.client_id, message, reply_to: id }
+
+            (pending, { effects & to_frontend })
+
+        HttpRequest request on_response ->
+            id = pending.next_id
+            http_requests = List.append effects.http_requests (Internal.Http.to_host_request id request)
+
+            (
+                { pending & next_id: id + 1, http: Dict.insert pending.http id on_response },
+                { effects & http_requests },
+            )
+
+        DbRequest statements on_result ->
+            id = pending.next_id
+            db_requests = List.append effects.db_requests (Internal.Db.to_host_request id statements)
+
+            (
+                { pending & next_id: id + 1, db: Dict.insert pending.db id on_result },
+                { effects & db_requests },
+            )
+
+        EnqueueJob job ->
+            (pending, { effects & jobs: List.append effects.jobs job })
+
+        Subscribe client_id topic ->
+            (pending, { effects & topics: List.append effects.topics { client_id, topic, subscribe: Bool.true } })
+
+        Unsubscribe client_id topic ->
+            (pending, { effects & topics: List.append effects.topics { client_id, topic, subscribe: Bool.false } })
+
+        Publish topic msg ->
+            message = Str.from_utf8_lossy (encode_to_frontend_msg msg)
+
+            (pending, { effects & published: List.append effects.published { topic, message } })
+
+        Disconnect target code reason ->
+            disconnect =
+                when target is
+                    Client id -> { id, session: Bool.false, code, reason }
+                    Session id -> { id, session: Bool.true, code, reason }
+
+            (pending, { effects & disconnects: List.append effects.disconnects disconnect })
+
+take_http_handler : Pending msg, U64 -> Result (Pending msg, Result Response Error -> msg) [NotFound]
+take_http_handler = |pending, id|
+    when Dict.get pending.http id is
+        Ok handler ->
+            Ok ({ pending & http: Dict.remove pending.http id }, handler)
+
+        Err KeyNotFound ->
+            Err NotFound
+
+take_db_handler : Pending msg, U64 -> Result (Pending msg, Result (List Internal.Db.QueryResult) Internal.Db.Error -> msg) [NotFound]
+take_db_handler = |pending, id|
+    when Dict.get pending.db id is
+        Ok handler ->
+            Ok ({ pending & db: Dict.remove pending.db id }, handler)
+
+        Err KeyNotFound ->
+            Err NotFound
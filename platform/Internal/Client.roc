@@ -0,0 +1,46 @@
+module [
+    Client,
+    HostClient,
+    from_host_client,
+]
+
+# A websocket, or a request to `/_galena/msg`, talking to the backend. `address` is the
+# client's ip address, taken from `X-Forwarded-For` when the connection comes from a
+# trusted proxy. `identity` is the user the proxy authenticated, if it is configured to
+# tell. `connected_at_ms` is in milliseconds since the unix epoch and `metadata` holds
+# anything else the host knows about the connection
+Client : {
+    client_id : Str,
+    session_id : Str,
+    address : Str,
+    user_agent : Str,
+    accept_language : Str,
+    identity : Result Str [Anonymous],
+    connected_at_ms : U64,
+    metadata : Dict Str Str,
+}
+
+# A client as handed over by the host, with an empty `identity` for anonymous clients
+HostClient : {
+    client_id : Str,
+    session_id : Str,
+    address : Str,
+    user_agent : Str,
+    accept_language : Str,
+    identity : Str,
+    connected_at_ms : U64,
+    metadata : List { name : Str, value : Str },
+}
+
+from_host_client : HostClient -> Client
+from_host_client = |host_client|
+    {
+        client_id: host_client.client_id,
+        session_id: host_client.session_id,
+        address: host_client.address,
+        user_agent: host_client.user_agent,
+        accept_language: host_client.accept_language,
+        identity: if Str.is_empty host_client.identity then Err Anonymous else Ok host_client.identity,
+        connected_at_ms: host_client.connected_at_ms,
+        metadata: List.walk host_client.metadata (Dict.empty {}) (|dict, { name, value }| Dict.insert dict name value),
+    }
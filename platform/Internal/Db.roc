@@ -0,0 +1,100 @@
+module [
+    Value,
+    Statement,
+    QueryResult,
+    Error,
+    HostRequest,
+    HostResponse,
+    to_host_request,
+    from_host_response,
+]
+
+Value : [Null, Integer I64, Real F64, Text Str, Blob (List U8)]
+
+Statement : { sql : Str, params : List Value }
+
+QueryResult : {
+    columns : List Str,
+    rows : List (List Value),
+    rows_affected : U64,
+    last_insert_id : I64,
+}
+
+Error : [NoDatabase, SqlError Str]
+
+# A value as handed to and from the host. `kind` is 0 for null, 1 for an integer, 2 for a
+# real, 3 for text and 4 for a blob. Text is stored as utf8 in `bytes`
+HostValue : {
+    kind : U8,
+    integer : I64,
+    real : F64,
+    bytes : List U8,
+}
+
+HostStatement : { sql : Str, params : List HostValue }
+
+# Statements the host runs in a single transaction. The id is echoed back with the
+# response so the platform can find the handler that should receive it
+HostRequest : {
+    id : U64,
+    statements : List HostStatement,
+}
+
+HostQueryResult : {
+    columns : List Str,
+    rows : List (List HostValue),
+    rows_affected : U64,
+    last_insert_id : I64,
+}
+
+# The outcome of a transaction as reported by the host. `failure` is 0 when every
+# statement succeeded, 1 when the app has no database configured and 2 for an error from
+# sqlite, in which case `error` describes it and the transaction was rolled back
+HostResponse : {
+    results : List HostQueryResult,
+    failure : U8,
+    error : Str,
+}
+
+to_host_request : U64, List Statement -> HostRequest
+to_host_request = |id, statements|
+    {
+        id,
+        statements: List.map
+            statements
+            (|{ sql, params }| { sql, params: List.map params to_host_value }),
+    }
+
+from_host_response : HostResponse -> Result (List QueryResult) Error
+from_host_response = |{ results, failure, error }|
+    when failure is
+        0 ->
+            Ok
+                (
+                    List.map
+                        results
+                        (|result| { result & rows: List.map result.rows (|row| List.map row from_host_value) })
+                )
+
+        1 -> Err NoDatabase
+        _ -> Err (SqlError error)
+
+to_host_value : Value -> HostValue
+to_host_value = |value|
+    empty = { kind: 0, integer: 0, real: 0, bytes: [] }
+
+    when value is
+        Null -> empty
+        Integer integer -> { empty & kind: 1, integer }
+        Real real -> { empty & kind: 2, real }
+        Text text -> { empty & kind: 3, bytes: Str.to_utf8 text }
+        Blob bytes -> { empty & kind: 4, bytes }
+
+from_host_value : HostValue -> Value
+from_host_value = |{ kind, integer, real, bytes }|
+    when kind is
+        1 -> Integer integer
+        2 -> Real real
+        3 -> Text (Str.from_utf8_lossy bytes)
+        4 -> Blob bytes
+        _ -> Null
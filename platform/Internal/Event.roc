@@ -0,0 +1,109 @@
+module [
+    Decoder,
+    EventProperty,
+    HostEventValue,
+    properties,
+    decode,
+    succeed_,
+    bool_,
+    number_,
+    string_,
+    json_,
+    maybe_,
+    map_,
+    map2_,
+    map3_,
+]
+
+# What the host reads from an event for a decoder: the path of the property, starting at
+# the event, and the kind of value it has to be. Events where a property that is not
+# `optional` is missing or of another kind are ignored
+EventProperty : {
+    path : List Str,
+    kind : U8,
+    optional : Bool,
+}
+
+# A property as read by the host, `kind` is 0 when it was missing or of another kind.
+# Booleans are given as a `number` of 0 or 1 and json as its `text`
+HostEventValue : {
+    kind : U8,
+    number : F64,
+    text : Str,
+}
+
+# Reads the values of `properties` in the same order
+Decoder a := {
+    properties : List EventProperty,
+    decode : List HostEventValue -> a,
+}
+
+kind_missing = 0
+kind_bool = 1
+kind_number = 2
+kind_string = 3
+kind_json = 4
+
+properties : Decoder a -> List EventProperty
+properties = |@Decoder decoder| decoder.properties
+
+decode : Decoder a, List HostEventValue -> a
+decode = |@Decoder decoder, values| decoder.decode values
+
+succeed_ : a -> Decoder a
+succeed_ = |value| @Decoder { properties: [], decode: |_| value }
+
+property : List Str, U8, (HostEventValue -> a) -> Decoder a
+property = |path, kind, from_value|
+    @Decoder {
+        properties: [{ path, kind, optional: Bool.false }],
+        decode: |values|
+            when values is
+                [value] -> from_value value
+                # The host always hands back one value per property
+                _ -> from_value { kind: kind_missing, number: 0, text: "" },
+    }
+
+bool_ : List Str -> Decoder Bool
+bool_ = |path| property path kind_bool |value| value.number != 0
+
+number_ : List Str -> Decoder F64
+number_ = |path| property path kind_number .number
+
+string_ : List Str -> Decoder Str
+string_ = |path| property path kind_string .text
+
+json_ : List Str -> Decoder Str
+json_ = |path| property path kind_json .text
+
+maybe_ : Decoder a -> Decoder (Result a [Missing])
+maybe_ = |@Decoder decoder|
+    @Decoder {
+        properties: List.map decoder.properties |prop| { prop & optional: Bool.true },
+        decode: |values|
+            if List.any values |value| value.kind == kind_missing then
+                Err Missing
+            else
+                Ok (decoder.decode values),
+    }
+
+map_ : Decoder a, (a -> b) -> Decoder b
+map_ = |@Decoder decoder, f|
+    @Decoder {
+        properties: decoder.properties,
+        decode: |values| f (decoder.decode values),
+    }
+
+map2_ : Decoder a, Decoder b, (a, b -> c) -> Decoder c
+map2_ = |@Decoder first, @Decoder second, f|
+    count = List.len first.properties
+
+    @Decoder {
+        properties: List.concat first.properties second.properties,
+        decode: |values|
+            f (first.decode (List.take_first values count)) (second.decode (List.drop_first values count)),
+    }
+
+map3_ : Decoder a, Decoder b, Decoder c, (a, b, c -> d) -> Decoder d
+map3_ = |first, second, third, f|
+    map2_ (map2_ first second |a, b| (a, b)) third |(a, b), c| f a b c
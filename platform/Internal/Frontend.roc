@@ -1,20 +1,36 @@
-module [InternalFrontend, frontend_, inner]
+module [InternalFrontend, FromBackend, ConnectionStatus, HostConnectionStatus, frontend_, with_offline_storage_, with_connection_change_, from_host_connection_status, inner]
 
 import Html exposing [Html]
 import json.Json as Json
+import Internal.FrontendCmd exposing [FrontendCmd]
 
 InternalFrontend model msg toFrontendMsg toBackendMsg := {
     init! : model,
-    update! : msg, model => (model, Result toBackendMsg [NoOp]),
+    update! : msg, model => (model, FrontendCmd msg toFrontendMsg toBackendMsg),
     view : model -> Html msg,
     updateFromBackend : toFrontendMsg -> msg,
+    # Whether messages waiting for the websocket to come back are kept in `localStorage`
+    offline_storage : Bool,
+    on_connection_change : Result (ConnectionStatus -> msg) [NoConnectionChange],
     encode_to_backend_msg : toBackendMsg -> List U8,
-    decode_to_frontend_msg : List U8 -> toFrontendMsg,
+    decode_from_backend : List U8 -> FromBackend toFrontendMsg,
 }
 
+# What the backend host sends over the websocket. `reply_to` is the id of the request a
+# message answers or 0 for messages the backend sends on its own
+FromBackend toFrontendMsg : { reply_to : U64, msg : toFrontendMsg }
+
+# The state of the websocket to the backend. `Reconnecting` carries the number of the attempt,
+# starting at 1, and `Disconnected` the reason the connection is gone for good
+ConnectionStatus : [Connecting, Connected, Reconnecting U32, Disconnected Str]
+
+# The connection status as handed over by the host. `kind` is 0 for connecting, 1 for
+# connected, 2 for reconnecting and 3 for disconnected
+HostConnectionStatus : { kind : U8, attempt : U32, reason : Str }
+
 FrontendAppSpec model msg toFrontendMsg toBackendMsg : {
     init! : model,
-    update! : msg, model => (model, Result toBackendMsg [NoOp]),
+    update! : msg, model => (model, FrontendCmd msg toFrontendMsg toBackendMsg),
     view : model -> Html msg,
     updateFromBackend : toFrontendMsg -> msg,
 }
@@ -26,14 +42,32 @@ frontend_ = |orig|
         update!: orig.update!,
         view: orig.view,
         updateFromBackend: orig.updateFromBackend,
+        offline_storage: Bool.false,
+        on_connection_change: Err NoConnectionChange,
         encode_to_backend_msg: |to_backend_msg| Encode.to_bytes to_backend_msg Json.utf8,
-        decode_to_frontend_msg: |msg_bytes|
+        decode_from_backend: |msg_bytes|
             when Decode.from_bytes msg_bytes Json.utf8 is
-                Ok msg ->
-                    msg
+                Ok from_backend ->
+                    from_backend
 
                 Err _ ->
                     crash "Unable to decode toFrontendMsg This is is a platform bug",
     }
 
+with_offline_storage_ : InternalFrontend model msg toFrontendMsg toBackendMsg -> InternalFrontend model msg toFrontendMsg toBackendMsg
+with_offline_storage_ = |@InternalFrontend frontend|
+    @InternalFrontend { frontend & offline_storage: Bool.true }
+
+with_connection_change_ : InternalFrontend model msg toFrontendMsg toBackendMsg, (ConnectionStatus -> msg) -> InternalFrontend model msg toFrontendMsg toBackendMsg
+with_connection_change_ = |@InternalFrontend frontend, on_connection_change|
+    @InternalFrontend { frontend & on_connection_change: Ok on_connection_change }
+
+from_host_connection_status : HostConnectionStatus -> ConnectionStatus
+from_host_connection_status = |{ kind, attempt, reason }|
+    when kind is
+        0 -> Connecting
+        1 -> Connected
+        2 -> Reconnecting attempt
+        _ -> Disconnected reason
+
 inner = |@InternalFrontend i| i
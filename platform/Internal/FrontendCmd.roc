@@ -0,0 +1,183 @@
+module [
+    FrontendCmd,
+    Pending,
+    RequestError,
+    DomError,
+    ToBackend,
+    HostEffect,
+    none_,
+    batch_,
+    send_to_backend_,
+    request_,
+    delay_,
+    focus_,
+    blur_,
+    scroll_into_view_,
+    log_,
+    empty_pending,
+    run,
+    take_request_handler,
+    take_effect_handler,
+]
+
+FrontendCmd msg to_frontend_msg to_backend_msg := [
+    None,
+    Batch (List (FrontendCmd msg to_frontend_msg to_backend_msg)),
+    SendToBackend to_backend_msg,
+    Request to_backend_msg [TimeoutMilliseconds U64, NoTimeout] (Result to_frontend_msg RequestError -> msg),
+    Delay U64 msg,
+    Focus Str (Result {} DomError -> msg),
+    Blur Str (Result {} DomError -> msg),
+    ScrollIntoView Str (Result {} DomError -> msg),
+    Log Str,
+]
+
+RequestError : [Timeout]
+
+# The element with the id was not on the page after the render that followed the update
+DomError : [NotFound Str]
+
+# Handlers for requests that are still waiting on a reply from the backend, looked up by
+# the id the backend echoes back, and for the other commands whose outcome the host reports
+# back. Ids start at 1 as 0 marks messages and effects that expect no reply
+Pending msg to_frontend_msg : {
+    next_id : U64,
+    requests : Dict U64 (Result to_frontend_msg RequestError -> msg),
+    # Called with whether the host found the element
+    effects : Dict U64 (Bool -> msg),
+}
+
+# A message the host should send over the websocket
+ToBackend : {
+    request_id : U64,
+    message : Str,
+    # 0 means no timeout
+    timeout_ms : U64,
+}
+
+# Something the host should do in the browser. `kind` is 0 to wait for `delay_ms`, 1 to
+# focus, 2 to blur or 3 to scroll into view the element whose id is `text`, and 4 to log
+# `text` to the console. Elements are looked up once the update has been rendered
+HostEffect : {
+    id : U64,
+    kind : U8,
+    delay_ms : U64,
+    text : Str,
+}
+
+none_ : FrontendCmd msg to_frontend_msg to_backend_msg
+none_ = @FrontendCmd None
+
+batch_ : List (FrontendCmd msg to_frontend_msg to_backend_msg) -> FrontendCmd msg to_frontend_msg to_backend_msg
+batch_ = |cmds| @FrontendCmd (Batch cmds)
+
+send_to_backend_ : to_backend_msg -> FrontendCmd msg to_frontend_msg to_backend_msg
+send_to_backend_ = |msg| @FrontendCmd (SendToBackend msg)
+
+request_ : to_backend_msg, [TimeoutMilliseconds U64, NoTimeout], (Result to_frontend_msg RequestError -> msg) -> FrontendCmd msg to_frontend_msg to_backend_msg
+request_ = |msg, timeout, on_reply| @FrontendCmd (Request msg timeout on_reply)
+
+delay_ : U64, msg -> FrontendCmd msg to_frontend_msg to_backend_msg
+delay_ = |milliseconds, msg| @FrontendCmd (Delay milliseconds msg)
+
+focus_ : Str, (Result {} DomError -> msg) -> FrontendCmd msg to_frontend_msg to_backend_msg
+focus_ = |id, on_done| @FrontendCmd (Focus id on_done)
+
+blur_ : Str, (Result {} DomError -> msg) -> FrontendCmd msg to_frontend_msg to_backend_msg
+blur_ = |id, on_done| @FrontendCmd (Blur id on_done)
+
+scroll_into_view_ : Str, (Result {} DomError -> msg) -> FrontendCmd msg to_frontend_msg to_backend_msg
+scroll_into_view_ = |id, on_done| @FrontendCmd (ScrollIntoView id on_done)
+
+log_ : Str -> FrontendCmd msg to_frontend_msg to_backend_msg
+log_ = |text| @FrontendCmd (Log text)
+
+empty_pending : Pending msg to_frontend_msg
+empty_pending = { next_id: 1, requests: Dict.empty {}, effects: Dict.empty {} }
+
+# Flattens a command into the messages the host should send and the effects it should
+# carry out, registering handlers for the replies to any requests and for the outcome of
+# any effects
+run : FrontendCmd msg to_frontend_msg to_backend_msg, Pending msg to_frontend_msg, (to_backend_msg -> List U8) -> { pending : Pending msg to_frontend_msg, to_backend : List ToBackend, effects : List HostEffect }
+run = |cmd, pending, encode_to_backend_msg|
+    run_help cmd { pending, to_backend: [], effects: [] } encode_to_backend_msg
+
+run_help = |@FrontendCmd cmd, { pending, to_backend, effects }, encode_to_backend_msg|
+    when cmd is
+        None ->
+            { pending, to_backend, effects }
+
+        Batch cmds ->
+            List.walk
+                cmds
+                { pending, to_backend, effects }
+                (|acc, c| run_help c acc encode_to_backend_msg)
+
+        SendToBackend msg ->
+            message = Str.from_utf8_lossy (encode_to_backend_msg msg)
+
+            { pending, to_backend: List.append to_backend { request_id: 0, message, timeout_ms: 0 }, effects }
+
+        Request msg timeout on_reply ->
+            id = pending.next_id
+            message = Str.from_utf8_lossy (encode_to_backend_msg msg)
+            timeout_ms =
+                when timeout is
+                    TimeoutMilliseconds ms -> ms
+                    NoTimeout -> 0
+
+            {
+                pending: { pending & next_id: id + 1, requests: Dict.insert pending.requests id on_reply },
+                to_backend: List.append to_backend { request_id: id, message, timeout_ms },
+                effects,
+            }
+
+        Delay milliseconds msg ->
+            add_effect { pending, to_backend, effects } 0 milliseconds "" |_| msg
+
+        Focus element_id on_done ->
+            add_effect { pending, to_backend, effects } 1 0 element_id (dom_outcome element_id on_done)
+
+        Blur element_id on_done ->
+            add_effect { pending, to_backend, effects } 2 0 element_id (dom_outcome element_id on_done)
+
+        ScrollIntoView element_id on_done ->
+            add_effect { pending, to_backend, effects } 3 0 element_id (dom_outcome element_id on_done)
+
+        Log text ->
+            { pending, to_backend, effects: List.append effects { id: 0, kind: 4, delay_ms: 0, text } }
+
+dom_outcome : Str, (Result {} DomError -> msg) -> (Bool -> msg)
+dom_outcome = |element_id, on_done|
+    |found|
+        if found then
+            on_done (Ok {})
+        else
+            on_done (Err (NotFound element_id))
+
+add_effect = |{ pending, to_backend, effects }, kind, delay_ms, text, on_done|
+    id = pending.next_id
+
+    {
+        pending: { pending & next_id: id + 1, effects: Dict.insert pending.effects id on_done },
+        to_backend,
+        effects: List.append effects { id, kind, delay_ms, text },
+    }
+
+take_request_handler : Pending msg to_frontend_msg, U64 -> Result (Pending msg to_frontend_msg, Result to_frontend_msg RequestError -> msg) [NotFound]
+take_request_handler = |pending, id|
+    when Dict.get pending.requests id is
+        Ok handler ->
+            Ok ({ pending & requests: Dict.remove pending.requests id }, handler)
+
+        Err KeyNotFound ->
+            Err NotFound
+
+take_effect_handler : Pending msg to_frontend_msg, U64 -> Result (Pending msg to_frontend_msg, Bool -> msg) [NotFound]
+take_effect_handler = |pending, id|
+    when Dict.get pending.effects id is
+        Ok handler ->
+            Ok ({ pending & effects: Dict.remove pending.effects id }, handler)
+
+        Err KeyNotFound ->
+            Err NotFound
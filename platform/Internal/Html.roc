@@ -2,6 +2,8 @@ module [
     InternalHtml,
     map_,
     text_,
+    keyed_,
+    lazy_keyed_,
     div_,
     p_,
     span_,
@@ -55,6 +57,17 @@ InternalHtml msg := [
             attrs : List (InternalAttr msg),
             children : List (InternalHtml msg),
         },
+    # Children carry a key that should be unique among them, the host uses it to tell which
+    # child is which when the list changes
+    Keyed
+        {
+            tag : Str,
+            attrs : List (InternalAttr msg),
+            children : List (Str, InternalHtml msg),
+        },
+    # A subtree the host only renders when its key is not the one it rendered last time at
+    # this spot
+    Lazy ({} -> InternalHtml msg) Str,
 ]
 
 # Helper to create elements
@@ -89,10 +102,34 @@ map_ = |html, mapper|
                     }
                 )
 
+        @InternalHtml (Keyed { tag, attrs, children }) ->
+            mappedAttrs = List.map(attrs, |attr| Attr.map attr mapper)
+            mappedChildren = List.map(children, |(key, child)| (key, map_ child mapper))
+
+            @InternalHtml
+                (
+                    Keyed {
+                        tag,
+                        attrs: mappedAttrs,
+                        children: mappedChildren,
+                    }
+                )
+
+        @InternalHtml (Lazy render key) ->
+            @InternalHtml (Lazy (|{}| map_ (render {}) mapper) key)
+
 # Text node
 text_ : Str -> InternalHtml msg
 text_ = |t| @InternalHtml (Text t)
 
+# Element whose children are identified by key rather than position
+keyed_ : Str, List (InternalAttr msg), List (Str, InternalHtml msg) -> InternalHtml msg
+keyed_ = |tag, attrs, children| @InternalHtml (Keyed { tag, attrs, children })
+
+# Subtree rendered by the host only when `key` changes
+lazy_keyed_ : Str, (a -> InternalHtml msg), a -> InternalHtml msg
+lazy_keyed_ = |key, view, arg| @InternalHtml (Lazy (|{}| view arg) key)
+
 # Basic elements
 div_ : List (InternalAttr msg), List (InternalHtml msg) -> InternalHtml msg
 div_ = |attrs, children| createElement "div" attrs children
@@ -0,0 +1,106 @@
+module [
+    Method,
+    Header,
+    Request,
+    Response,
+    Error,
+    HostRequest,
+    HostResponse,
+    to_host_request,
+    from_host_response,
+    method_from_str,
+]
+
+Method : [Options, Get, Post, Put, Delete, Head, Trace, Connect, Patch]
+
+Header : { name : Str, value : Str }
+
+Request : {
+    method : Method,
+    url : Str,
+    headers : List Header,
+    body : List U8,
+    timeout : [TimeoutMilliseconds U64, NoTimeout],
+}
+
+Response : {
+    status : U16,
+    headers : List Header,
+    body : List U8,
+}
+
+Error : [Timeout, NetworkError Str, BadRequest Str]
+
+# The request as handed to the host. The id is echoed back with the response so the
+# platform can find the handler that should receive it
+HostRequest : {
+    id : U64,
+    method : Str,
+    url : Str,
+    headers : List Header,
+    body : List U8,
+    # 0 means no timeout
+    timeout_ms : U64,
+}
+
+# The outcome of a request as reported by the host. `failure` is 0 when a response was
+# received, otherwise 1 for a timeout, 2 for a network error and 3 for a request the
+# host could not build, in which case `error` describes what went wrong
+HostResponse : {
+    status : U16,
+    headers : List Header,
+    body : List U8,
+    failure : U8,
+    error : Str,
+}
+
+to_host_request : U64, Request -> HostRequest
+to_host_request = |id, { method, url, headers, body, timeout }|
+    timeout_ms =
+        when timeout is
+            TimeoutMilliseconds ms -> ms
+            NoTimeout -> 0
+
+    {
+        id,
+        method: method_to_str method,
+        url,
+        headers,
+        body,
+        timeout_ms,
+    }
+
+from_host_response : HostResponse -> Result Response Error
+from_host_response = |{ status, headers, body, failure, error }|
+    when failure is
+        0 -> Ok { status, headers, body }
+        1 -> Err Timeout
+        2 -> Err (NetworkError error)
+        _ -> Err (BadRequest error)
+
+method_to_str : Method -> Str
+method_to_str = |method|
+    when method is
+        Options -> "OPTIONS"
+        Get -> "GET"
+        Post -> "POST"
+        Put -> "PUT"
+        Delete -> "DELETE"
+        Head -> "HEAD"
+        Trace -> "TRACE"
+        Connect -> "CONNECT"
+        Patch -> "PATCH"
+
+method_from_str : Str -> Result Method [UnknownMethod]
+method_from_str = |str|
+    when str is
+        "OPTIONS" -> Ok Options
+        "GET" -> Ok Get
+        "POST" -> Ok Post
+        "PUT" -> Ok Put
+        "DELETE" -> Ok Delete
+        "HEAD" -> Ok Head
+        "TRACE" -> Ok Trace
+        "CONNECT" -> Ok Connect
+        "PATCH" -> Ok Patch
+        _ -> Err UnknownMethod
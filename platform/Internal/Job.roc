@@ -0,0 +1,131 @@
+module [
+    Job,
+    Error,
+    Retry,
+    HostJob,
+    HostOutcome,
+    HostCompletion,
+    define_,
+    job_name,
+    to_host_job,
+    run,
+    complete,
+]
+
+import json.Json
+
+# A job registered with `Backend.with_jobs`. Payloads and outputs cross the host as json
+# so that queued jobs survive a restart, which is also why completion is mapped to a
+# message by the definition rather than by a closure captured when the job was enqueued
+Job msg := {
+    name : Str,
+    run : List U8 -> Result (List U8) [InvalidPayload, Failed Str],
+    complete : List U8, Result (List U8) Error -> Result msg [InvalidPayload],
+}
+
+Error : [Failed { attempts : U32, reason : Str }, InvalidPayload]
+
+Retry : { max_attempts : U32, initial_backoff_ms : U64 }
+
+# A job as handed to the host to be queued
+HostJob : {
+    name : Str,
+    payload : List U8,
+    max_attempts : U32,
+    initial_backoff_ms : U64,
+}
+
+# The result of running a job once. `failure` is 0 on success, 1 when the job failed and
+# may be retried, 2 when its payload could not be decoded and 3 when no job of that name
+# is registered
+HostOutcome : {
+    output : List U8,
+    error : Str,
+    failure : U8,
+}
+
+# A job the host is done with, either because it succeeded or ran out of attempts
+HostCompletion : {
+    name : Str,
+    payload : List U8,
+    output : List U8,
+    error : Str,
+    failure : U8,
+    attempts : U32,
+}
+
+define_ : Str, (input -> Result output Str), (input, Result output Error -> msg) -> Job msg where input implements Decoding, output implements Encoding & Decoding
+define_ = |name, run_job, on_done|
+    @Job {
+        name,
+        run: |payload|
+            when Decode.from_bytes payload Json.utf8 is
+                Ok input ->
+                    run_job input
+                    |> Result.map_ok (|output| Encode.to_bytes output Json.utf8)
+                    |> Result.map_err Failed
+
+                Err _ ->
+                    Err InvalidPayload,
+        complete: |payload, result|
+            when Decode.from_bytes payload Json.utf8 is
+                Ok input ->
+                    decoded =
+                        Result.try
+                            result
+                            (|output|
+                                Decode.from_bytes output Json.utf8
+                                |> Result.map_err (|_| InvalidPayload))
+
+                    Ok (on_done input decoded)
+
+                Err _ ->
+                    Err InvalidPayload,
+    }
+
+job_name : Job msg -> Str
+job_name = |@Job job| job.name
+
+to_host_job : Str, input, Retry -> HostJob where input implements Encoding
+to_host_job = |name, input, { max_attempts, initial_backoff_ms }|
+    {
+        name,
+        payload: Encode.to_bytes input Json.utf8,
+        max_attempts,
+        initial_backoff_ms,
+    }
+
+run : List (Job msg), Str, List U8 -> HostOutcome
+run = |jobs, name, payload|
+    when find jobs name is
+        Ok job ->
+            when job.run payload is
+                Ok output -> { output, error: "", failure: 0 }
+                Err (Failed reason) -> { output: [], error: reason, failure: 1 }
+                Err InvalidPayload -> { output: [], error: "", failure: 2 }
+
+        Err NotFound ->
+            { output: [], error: "", failure: 3 }
+
+# The message for a finished job, if it is still registered and its payload decodes
+complete : List (Job msg), HostCompletion -> Result msg [NotFound, InvalidPayload]
+complete = |jobs, { name, payload, output, error, failure, attempts }|
+    when find jobs name is
+        Ok job ->
+            result =
+                when failure is
+                    0 -> Ok output
+                    2 -> Err InvalidPayload
+                    _ -> Err (Failed { attempts, reason: error })
+
+            when job.complete payload result is
+                Ok msg -> Ok msg
+                Err InvalidPayload -> Err InvalidPayload
+
+        Err NotFound ->
+            Err NotFound
+
+find = |jobs, name|
+    List.find_first jobs (|@Job job| job.name == name)
+    |> Result.map_ok (|@Job job| job)
+    |> Result.map_err (|_| NotFound)
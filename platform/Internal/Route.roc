@@ -0,0 +1,153 @@
+module [
+    Route,
+    Request,
+    Response,
+    Outcome,
+    HostRequest,
+    HostResponse,
+    route_,
+    find,
+]
+
+import Internal.Http exposing [Method, Header]
+
+Route model msg := {
+    method : Method,
+    pattern : List Str,
+    handler : Request, model -> Outcome model msg,
+}
+
+Request : {
+    method : Method,
+    path : Str,
+    # Captured `:name` segments of the route pattern, plus "*" for a trailing wildcard. They
+    # are percent decoded, `path` is not
+    params : Dict Str Str,
+    query : Dict Str Str,
+    headers : List Header,
+    body : List U8,
+}
+
+Response : {
+    status : U16,
+    headers : List Header,
+    body : List U8,
+}
+
+# `Respond` answers without touching the backend model. `Update` first runs the message
+# through the backend's `update!` and then builds the response from the updated model
+Outcome model msg : [
+    Respond Response,
+    Update msg (model -> Response),
+]
+
+# The request as received by the host. Query parameters are already percent decoded
+HostRequest : {
+    method : Str,
+    path : Str,
+    query : List Header,
+    headers : List Header,
+    body : List U8,
+}
+
+# Handed back to the host as is
+HostResponse : Response
+
+route_ : Method, Str, (Request, model -> Outcome model msg) -> Route model msg
+route_ = |method, path, handler|
+    @Route { method, pattern: segments path, handler }
+
+# Finds the first route matching the request's method and path
+find : List (Route model msg), HostRequest -> Result (Request, (Request, model -> Outcome model msg)) [NotFound]
+find = |routes, host_request|
+    when Internal.Http.method_from_str host_request.method is
+        Ok method ->
+            path_segments = segments host_request.path
+
+            List.walk_until
+                routes
+                (Err NotFound)
+                (|_, @Route route|
+                    if route.method == method then
+                        when match_segments route.pattern path_segments (Dict.empty {}) is
+                            Ok params ->
+                                request = {
+                                    method,
+                                    path: host_request.path,
+                                    params,
+                                    query: List.walk host_request.query (Dict.empty {}) (|query, { name, value }| Dict.insert query name value),
+                                    headers: host_request.headers,
+                                    body: host_request.body,
+                                }
+
+                                Break (Ok (request, route.handler))
+
+                            Err NoMatch ->
+                                Continue (Err NotFound)
+                    else
+                        Continue (Err NotFound))
+
+        Err UnknownMethod ->
+            Err NotFound
+
+segments : Str -> List Str
+segments = |path|
+    Str.split_on path "/"
+    |> List.drop_if Str.is_empty
+
+match_segments : List Str, List Str, Dict Str Str -> Result (Dict Str Str) [NoMatch]
+match_segments = |pattern, path_segments, params|
+    when (pattern, path_segments) is
+        ([], []) ->
+            Ok params
+
+        (["*"], rest) ->
+            Ok (Dict.insert params "*" (Str.join_with (List.map rest percent_decode) "/"))
+
+        ([expected, .. as pattern_rest], [actual, .. as path_rest]) ->
+            if Str.starts_with expected ":" then
+                name = Str.drop_prefix expected ":"
+                match_segments pattern_rest path_rest (Dict.insert params name (percent_decode actual))
+            else if expected == actual then
+                match_segments pattern_rest path_rest params
+            else
+                Err NoMatch
+
+        _ ->
+            Err NoMatch
+
+# Decodes the `%XX` escapes of a path segment. Malformed escapes are kept as they are, and
+# so is the whole segment when it does not decode to utf8. Unlike in a query, "+" is not a
+# space in a path
+percent_decode : Str -> Str
+percent_decode = |segment|
+    if Str.contains segment "%" then
+        Str.from_utf8 (decode_escapes (Str.to_utf8 segment) [])
+        |> Result.with_default segment
+    else
+        segment
+
+decode_escapes : List U8, List U8 -> List U8
+decode_escapes = |bytes, decoded|
+    when bytes is
+        [percent, high, low, .. as rest] if percent == '%' ->
+            when (hex_value high, hex_value low) is
+                (Ok h, Ok l) -> decode_escapes rest (List.append decoded (h * 16 + l))
+                _ -> decode_escapes (List.drop_first bytes 1) (List.append decoded percent)
+
+        [byte, .. as rest] ->
+            decode_escapes rest (List.append decoded byte)
+
+        [] ->
+            decoded
+
+hex_value : U8 -> Result U8 [NotHex]
+hex_value = |byte|
+    if byte >= '0' && byte <= '9' then
+        Ok (byte - '0')
+    else if byte >= 'a' && byte <= 'f' then
+        Ok (byte - 'a' + 10)
+    else if byte >= 'A' && byte <= 'F' then
+        Ok (byte - 'A' + 10)
+    else
+        Err NotHex
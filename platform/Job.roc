@@ -0,0 +1,33 @@
+module [
+    Job,
+    Error,
+    Retry,
+    define,
+    default_retry,
+    enqueue,
+    enqueue_with_retry,
+]
+
+import Internal.BackendCmd exposing [BackendCmd, enqueue_job_]
+import Internal.Job exposing [define_, to_host_job]
+
+Job msg : Internal.Job.Job msg
+Error : Internal.Job.Error
+Retry : Internal.Job.Retry
+
+# Defines a job named `name` that the host runs off the update loop. `on_done` turns the
+# job's input and its outcome into a message once it succeeds or runs out of attempts
+define : Str, (input -> Result output Str), (input, Result output Error -> msg) -> Job msg where input implements Decoding, output implements Encoding & Decoding
+define = define_
+
+# Three attempts, waiting one second before the first retry and doubling after that
+default_retry : Retry
+default_retry = { max_attempts: 3, initial_backoff_ms: 1_000 }
+
+# Queues a run of the job registered as `name`. The queue is persisted, so jobs that have
+# not finished yet are picked up again after a restart
+enqueue : Str, input -> BackendCmd msg to_frontend_msg where input implements Encoding
+enqueue = |name, input| enqueue_with_retry name input default_retry
+
+enqueue_with_retry : Str, input, Retry -> BackendCmd msg to_frontend_msg where input implements Encoding
+enqueue_with_retry = |name, input, retry| enqueue_job_ (to_host_job name input retry)
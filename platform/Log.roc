@@ -0,0 +1,23 @@
+module [debug!, info!, warn!, error!]
+
+import Host
+
+# Each log takes a message and a list of key/value fields, e.g.
+# `Log.info! "Signed in" [("user", user_id)]`. On the backend these end up in the host's
+# tracing output, tagged with the client, session and message being handled. On the
+# frontend they go to the browser console, with the fields as an object
+
+# For detail that is only wanted while tracking a problem down. The backend writes it
+# unless `RUST_LOG` says otherwise, e.g. `RUST_LOG=app=info`, while browsers only show it
+# when the console is set to verbose
+debug! : Str, List (Str, Str) => {}
+debug! = |message, fields| Host.log! 0 message fields
+
+info! : Str, List (Str, Str) => {}
+info! = |message, fields| Host.log! 1 message fields
+
+warn! : Str, List (Str, Str) => {}
+warn! = |message, fields| Host.log! 2 message fields
+
+error! : Str, List (Str, Str) => {}
+error! = |message, fields| Host.log! 3 message fields
@@ -0,0 +1,105 @@
+module [
+    Route,
+    Request,
+    Response,
+    Outcome,
+    get,
+    post,
+    put,
+    patch,
+    delete,
+    route,
+    text,
+    json,
+    file,
+]
+
+import json.Json
+import Internal.Http exposing [Method]
+import Internal.Route exposing [route_]
+
+Route model msg : Internal.Route.Route model msg
+Request : Internal.Route.Request
+Response : Internal.Route.Response
+Outcome model msg : Internal.Route.Outcome model msg
+
+# Path patterns are split on "/". A segment starting with ":" captures that segment into
+# the request's `params` and a final "*" segment captures the remainder of the path
+route : Method, Str, (Request, model -> Outcome model msg) -> Route model msg
+route = route_
+
+get : Str, (Request, model -> Outcome model msg) -> Route model msg
+get = |path, handler| route_ Get path handler
+
+post : Str, (Request, model -> Outcome model msg) -> Route model msg
+post = |path, handler| route_ Post path handler
+
+put : Str, (Request, model -> Outcome model msg) -> Route model msg
+put = |path, handler| route_ Put path handler
+
+patch : Str, (Request, model -> Outcome model msg) -> Route model msg
+patch = |path, handler| route_ Patch path handler
+
+delete : Str, (Request, model -> Outcome model msg) -> Route model msg
+delete = |path, handler| route_ Delete path handler
+
+text : U16, Str -> Response
+text = |status, body|
+    {
+        status,
+        headers: [{ name: "Content-Type", value: "text/plain; charset=utf-8" }],
+        body: Str.to_utf8 body,
+    }
+
+json : U16, a -> Response where a implements Encoding
+json = |status, value|
+    {
+        status,
+        headers: [{ name: "Content-Type", value: "application/json" }],
+        body: Encode.to_bytes value Json.utf8,
+    }
+
+# A download of `bytes` saved as `file_name` by the browser
+file : Str, Str, List U8 -> Response
+file = |file_name, content_type, bytes|
+    {
+        status: 200,
+        headers: [
+            { name: "Content-Type", value: content_type },
+            { name: "Content-Disposition", value: content_disposition file_name },
+        ],
+        body: bytes,
+    }
+
+# The name goes in twice: percent encoded as `filename*` (RFC 6266), which browsers prefer,
+# and as a plain `filename` with everything that could end or break the quoted string
+# replaced, for clients that only know that one
+content_disposition : Str -> Str
+content_disposition = |file_name|
+    bytes = Str.to_utf8 file_name
+    fallback =
+        List.map bytes |byte|
+            if byte >= 0x20 && byte < 0x7f && byte != '"' && byte != '\\' then byte else '_'
+    encoded =
+        List.join_map bytes |byte|
+            if is_attr_char byte then
+                [byte]
+            else
+                ['%', hex_digit (Num.shift_right_zf_by byte 4), hex_digit (Num.bitwise_and byte 0xf)]
+
+    "attachment; filename=\"${ascii fallback}\"; filename*=UTF-8''${ascii encoded}"
+
+# The characters RFC 5987 allows unencoded in `filename*`
+is_attr_char : U8 -> Bool
+is_attr_char = |byte|
+    (byte >= 'a' && byte <= 'z')
+    || (byte >= 'A' && byte <= 'Z')
+    || (byte >= '0' && byte <= '9')
+    || List.contains ['!', '#', '$', '&', '+', '-', '.', '^', '_', '`', '|', '~'] byte
+
+hex_digit : U8 -> U8
+hex_digit = |nibble| if nibble < 10 then '0' + nibble else 'A' + nibble - 10
+
+# Only ever given ascii bytes
+ascii : List U8 -> Str
+ascii = |bytes| Str.from_utf8 bytes |> Result.with_default ""
@@ -9,11 +9,32 @@ platform "galena_platform"
         frontend_view_for_host,
         frontend_init_for_host,
         frontend_update_for_host,
-        frontend_decode_to_frontend_msg,
+        frontend_receive_for_host,
+        frontend_timeout_for_host,
+        frontend_effect_done_for_host,
+        frontend_connection_for_host,
+        frontend_config_for_host,
+        backend_init_for_host,
         backend_update_for_host,
+        backend_http_response_for_host,
+        backend_route_for_host,
+        backend_rpc_for_host,
+        backend_db_response_for_host,
+        backend_job_done_for_host,
+        backend_presence_for_host,
+        backend_run_job_for_host,
+        backend_config_for_host,
     ]
 
 import Internal.Html as Html
+import Internal.Http as Http
+import Internal.Backend as Backend
+import Internal.BackendCmd as BackendCmd
+import Internal.FrontendCmd as FrontendCmd
+import Internal.Route as Route
+import Internal.Db as Db
+import Internal.Job as Job
+import Internal.Client as Client
 
 frontend_init_for_host : U32 -> U32
 frontend_init_for_host = |model| model
@@ -24,23 +45,129 @@ frontend_view_for_host = |_| Html.text_ ""
 frontend_update_for_host : U32, U32 ->
     {
         model : U32,
-        to_backend : Result Str [NoOp],
+        to_backend : List FrontendCmd.ToBackend,
+        effects : List FrontendCmd.HostEffect,
     }
 frontend_update_for_host = |boxed_model, _|
     {
         model: boxed_model,
-        to_backend: Ok ""
+        to_backend: [],
+        effects: [],
     }
 
-frontend_decode_to_frontend_msg : List U8 -> U32
-frontend_decode_to_frontend_msg = |_| 0
- 
-backend_update_for_host : 
-    U64, Str, Str, Str -> 
-    { 
-        model : U64, 
-        to_frontend : Result { client_id: Str, message: Str } [NoOp] 
+frontend_receive_for_host : U32, List U8 ->
+    {
+        model : U32,
+        to_backend : List FrontendCmd.ToBackend,
+        effects : List FrontendCmd.HostEffect,
+    }
+frontend_receive_for_host = |boxed_model, _| { model: boxed_model, to_backend: [], effects: [] }
+
+frontend_timeout_for_host : U32, U64 ->
+    {
+        model : U32,
+        to_backend : List FrontendCmd.ToBackend,
+        effects : List FrontendCmd.HostEffect,
+    }
+frontend_timeout_for_host = |boxed_model, _| { model: boxed_model, to_backend: [], effects: [] }
+
+frontend_effect_done_for_host : U32, U64, Bool ->
+    {
+        model : U32,
+        to_backend : List FrontendCmd.ToBackend,
+        effects : List FrontendCmd.HostEffect,
+    }
+frontend_effect_done_for_host = |boxed_model, _, _| { model: boxed_model, to_backend: [], effects: [] }
+
+frontend_connection_for_host : U32, { kind : U8, attempt : U32, reason : Str } ->
+    {
+        model : U32,
+        to_backend : List FrontendCmd.ToBackend,
+        effects : List FrontendCmd.HostEffect,
+    }
+frontend_connection_for_host = |boxed_model, _| { model: boxed_model, to_backend: [], effects: [] }
+
+frontend_config_for_host : I32 -> { offline_storage : Bool, connection_status : Bool }
+frontend_config_for_host = |_| { offline_storage: Bool.false, connection_status: Bool.false }
+
+backend_init_for_host :
+    Backend.HostStartup ->
+    {
+        model : U64,
+        effects : BackendCmd.HostEffects,
+    }
+backend_init_for_host = |_| { model: 0, effects: BackendCmd.no_effects }
+
+backend_update_for_host :
+    U64, Client.HostClient, Str ->
+    {
+        model : U64,
+        effects : BackendCmd.HostEffects,
+    }
+backend_update_for_host = |_, _, _| { model: 0, effects: BackendCmd.no_effects }
+
+backend_http_response_for_host :
+    U64, U64, Http.HostResponse ->
+    {
+        model : U64,
+        effects : BackendCmd.HostEffects,
+    }
+backend_http_response_for_host = |_, _, _| { model: 0, effects: BackendCmd.no_effects }
+
+backend_route_for_host :
+    U64, Route.HostRequest ->
+    {
+        response : Result Route.HostResponse [NotFound],
+        update : {
+            model : U64,
+            effects : BackendCmd.HostEffects,
+        },
+    }
+backend_route_for_host = |_, _| {
+    response: Err NotFound,
+    update: { model: 0, effects: BackendCmd.no_effects },
+}
+
+backend_rpc_for_host :
+    U64, Client.HostClient, List U8 ->
+    {
+        decoded : Bool,
+        update : {
+            model : U64,
+            effects : BackendCmd.HostEffects,
+        },
+    }
+backend_rpc_for_host = |_, _, _| {
+    decoded: Bool.false,
+    update: { model: 0, effects: BackendCmd.no_effects },
+}
+
+backend_db_response_for_host :
+    U64, U64, Db.HostResponse ->
+    {
+        model : U64,
+        effects : BackendCmd.HostEffects,
+    }
+backend_db_response_for_host = |_, _, _| { model: 0, effects: BackendCmd.no_effects }
+
+backend_job_done_for_host :
+    U64, Job.HostCompletion ->
+    {
+        model : U64,
+        effects : BackendCmd.HostEffects,
+    }
+backend_job_done_for_host = |_, _| { model: 0, effects: BackendCmd.no_effects }
+
+backend_presence_for_host :
+    U64, List Client.HostClient ->
+    {
+        model : U64,
+        effects : BackendCmd.HostEffects,
     }
-backend_update_for_host = |_, _, _, _| { model: 0, to_frontend: Err NoOp }
+backend_presence_for_host = |_, _| { model: 0, effects: BackendCmd.no_effects }
 
+backend_run_job_for_host : Str, List U8 -> Job.HostOutcome
+backend_run_job_for_host = |_, _| { output: [], error: "", failure: 3 }
 
+backend_config_for_host : I32 -> { database : Str, jobs : List Str, presence : Bool }
+backend_config_for_host = |_| { database: "", jobs: [], presence: Bool.false }
@@ -9,6 +9,7 @@ app [
 ] { galena: platform "./main.roc" }
 
 import galena.Backend as Backend exposing [Backend]
+import galena.Cmd as Cmd
 import galena.Frontend as Frontend exposing [Frontend]
 import galena.Html as Html
 
@@ -27,15 +28,15 @@ frontendApp = Frontend.frontend {
     updateFromBackend: |_| {},
 }
 
-frontend_update! = |_, _| ({}, Err NoOp)
+frontend_update! = |_, _| ({}, Cmd.none)
 
 backendApp : Backend BackendModel {} ToFrontendMsg ToBackendMsg
 backendApp = Backend.backend {
-    init!: {},
-    update!: |_, model| (model, Err NoOp),
+    init!: |_| ({}, Backend.none),
+    update!: |_, model| (model, Backend.none),
     update_from_frontend: update_from_frontend,
 }
 
-update_from_frontend : Str, Str, toBackendMsg -> {}
-update_from_frontend = |_, _, _| {}
+update_from_frontend : Backend.Client, toBackendMsg -> {}
+update_from_frontend = |_, _| {}
 
@@ -3,63 +3,136 @@ platform "galena_platform"
         frontendApp : Frontend FrontendModel FrontendMsg ToFrontendMsg ToBackendMsg,
         backendApp : Backend BackendModel backendMsg ToFrontendMsg ToBackendMsg,
     }
-    exposes [Frontend, Backend]
+    exposes [Frontend, Backend, Cmd, Db, Env, Http, Job, Log, Route]
     packages {
         json: "https://github.com/lukewilliamboswell/roc-json/releases/download/0.13.0/RqendgZw5e1RsQa3kFhgtnMP8efWoqGRsAvubx4-zus.tar.br",
     }
     imports []
     provides [
-        frontend_decode_to_frontend_msg,
         frontend_init_for_host!,
         frontend_update_for_host!,
+        frontend_receive_for_host!,
+        frontend_timeout_for_host!,
+        frontend_effect_done_for_host!,
         frontend_view_for_host!,
+        frontend_connection_for_host!,
+        frontend_config_for_host,
         backend_init_for_host!,
         backend_update_for_host!,
+        backend_http_response_for_host!,
+        backend_route_for_host!,
+        backend_rpc_for_host!,
+        backend_db_response_for_host!,
+        backend_job_done_for_host!,
+        backend_presence_for_host!,
+        backend_run_job_for_host,
+        backend_config_for_host,
     ]
 
 import Backend exposing [Backend]
 import Frontend exposing [Frontend]
 import Internal.Backend
+import Internal.BackendCmd
+import Internal.Db
+import Internal.Job
+import Internal.Client
 import Internal.Frontend
+import Internal.FrontendCmd
+import Log
+import Internal.Http
+import Internal.Route
 import Html
 
-frontend_init_for_host! : I32 => Box FrontendModel
-frontend_init_for_host! = |_|
-    Box.box (Internal.Frontend.inner frontendApp).init!
+# The frontend model together with the handlers for requests that are still waiting on a
+# reply from the backend
+FrontendState : {
+    model : FrontendModel,
+    pending : Internal.FrontendCmd.Pending FrontendMsg ToFrontendMsg,
+}
 
-frontend_update_for_host! :
-    Box FrontendModel,
-    Box FrontendMsg
-    =>
-    {
-        model : Box FrontendModel,
-        to_backend : Result Str [NoOp],
+FrontendUpdate : {
+    model : Box FrontendState,
+    to_backend : List Internal.FrontendCmd.ToBackend,
+    effects : List Internal.FrontendCmd.HostEffect,
+}
+
+frontend_init_for_host! : I32 => Box FrontendState
+frontend_init_for_host! = |_|
+    Box.box {
+        model: (Internal.Frontend.inner frontendApp).init!,
+        pending: Internal.FrontendCmd.empty_pending,
     }
-frontend_update_for_host! = |boxed_model, boxed_msg|
+
+frontend_update_for_host! : Box FrontendState, Box FrontendMsg => FrontendUpdate
+frontend_update_for_host! = |boxed_state, boxed_msg|
+    update_frontend! (Box.unbox boxed_state) (Box.unbox boxed_msg)
+
+# Called by the host for every message received over the websocket
+frontend_receive_for_host! : Box FrontendState, List U8 => FrontendUpdate
+frontend_receive_for_host! = |boxed_state, msg_bytes|
+    state = Box.unbox boxed_state
     app = Internal.Frontend.inner frontendApp
-    model = Box.unbox boxed_model
-    msg = Box.unbox boxed_msg
-    
-    (updated_model, m_to_backend_msg) = app.update! msg model
-    {
-        model: Box.box updated_model,
-        to_backend: Result.map_ok
-            m_to_backend_msg
-            (|to_backend_msg|
-                app.encode_to_backend_msg to_backend_msg
-                |> Str.from_utf8_lossy
-            ),
-    }
+    { reply_to, msg } = app.decode_from_backend msg_bytes
+
+    if reply_to == 0 then
+        update_frontend! state (app.updateFromBackend msg)
+    else
+        when Internal.FrontendCmd.take_request_handler state.pending reply_to is
+            Ok (pending, on_reply) ->
+                update_frontend! { state & pending } (on_reply (Ok msg))
+
+            # The request already timed out
+            Err NotFound ->
+                { model: Box.box state, to_backend: [], effects: [] }
+
+# Called by the host once a request made with `Cmd.request` has waited too long for its reply
+frontend_timeout_for_host! : Box FrontendState, U64 => FrontendUpdate
+frontend_timeout_for_host! = |boxed_state, request_id|
+    state = Box.unbox boxed_state
+
+    when Internal.FrontendCmd.take_request_handler state.pending request_id is
+        Ok (pending, on_reply) ->
+            update_frontend! { state & pending } (on_reply (Err Timeout))
+
+        Err NotFound ->
+            { model: Box.box state, to_backend: [], effects: [] }
+
+# Called by the host once an effect of a command is done, with whether it found the
+# element the effect was for
+frontend_effect_done_for_host! : Box FrontendState, U64, Bool => FrontendUpdate
+frontend_effect_done_for_host! = |boxed_state, effect_id, found|
+    state = Box.unbox boxed_state
+
+    when Internal.FrontendCmd.take_effect_handler state.pending effect_id is
+        Ok (pending, on_done) ->
+            update_frontend! { state & pending } (on_done found)
 
-frontend_decode_to_frontend_msg : List U8 -> Box ToFrontendMsg
-frontend_decode_to_frontend_msg = | msg_bytes|
+        Err NotFound ->
+            { model: Box.box state, to_backend: [], effects: [] }
+
+# Called by the host whenever the websocket connects, drops or gives up, if the app asked for it
+frontend_connection_for_host! : Box FrontendState, Internal.Frontend.HostConnectionStatus => FrontendUpdate
+frontend_connection_for_host! = |boxed_state, host_status|
+    state = Box.unbox boxed_state
+
+    when (Internal.Frontend.inner frontendApp).on_connection_change is
+        Ok on_connection_change ->
+            update_frontend! state (on_connection_change (Internal.Frontend.from_host_connection_status host_status))
+
+        Err NoConnectionChange ->
+            { model: Box.box state, to_backend: [], effects: [] }
+
+update_frontend! : FrontendState, FrontendMsg => FrontendUpdate
+update_frontend! = |state, msg|
     app = Internal.Frontend.inner frontendApp
-    app.decode_to_frontend_msg msg_bytes
-        |> Box.box
-            
-frontend_view_for_host! : Box FrontendModel => Html.Html (Result (Box FrontendMsg) {})
+    (model, cmd) = app.update! msg state.model
+    { pending, to_backend, effects } = Internal.FrontendCmd.run cmd state.pending app.encode_to_backend_msg
+
+    { model: Box.box { model, pending }, to_backend, effects }
+
+frontend_view_for_host! : Box FrontendState => Html.Html (Result (Box FrontendMsg) {})
 frontend_view_for_host! = |boxed|
-    model = Box.unbox boxed
+    { model } = Box.unbox boxed
     app = Internal.Frontend.inner frontendApp
         
     # NOTE: This forces the alignment of the captured type to 8 so that calculations
@@ -76,40 +149,195 @@ frontend_view_for_host! = |boxed|
         Html.div [ Html.on_click (|_| Err (drop a)) , Html.attribute "hidden" "" ] []
     ]
 
+# Asked by the host on startup. `connection_status` is whether the app wants to hear about
+# the websocket connecting and disconnecting
+frontend_config_for_host : I32 -> { offline_storage : Bool, connection_status : Bool }
+frontend_config_for_host = |_|
+    app = Internal.Frontend.inner frontendApp
+
+    {
+        offline_storage: app.offline_storage,
+        connection_status: Result.is_ok app.on_connection_change,
+    }
+
+# The backend model together with the platform's bookkeeping for commands whose
+# results have not arrived yet
+BackendState msg : {
+    model : BackendModel,
+    pending : Internal.BackendCmd.Pending msg,
+}
+
+BackendUpdate msg : {
+    model : Box (BackendState msg),
+    effects : Internal.BackendCmd.HostEffects,
+}
 
-backend_init_for_host! : Box BackendModel
-backend_init_for_host! =
-    (Internal.Backend.inner backendApp).init!
-    |> Box.box
+backend_init_for_host! : Internal.Backend.HostStartup => BackendUpdate _
+backend_init_for_host! = |host_startup|
+    app = Internal.Backend.inner backendApp
+    (model, cmd) = app.init! (Internal.Backend.from_host_startup host_startup)
+    (pending, effects) = Internal.BackendCmd.run cmd Internal.BackendCmd.empty_pending app.encode_to_frontend_msg
+
+    { model: Box.box { model, pending }, effects }
 
 #  NOTE: Currently only called when we receive a message
 # TODO: Expand the circumstances in which this would be called e.g. with subscriptions
 #  or rename this to be more descriptive of this specific scenario
-backend_update_for_host! : 
-    Box BackendModel, Str, Str, Str => 
-    { 
-        model: Box BackendModel, 
-        to_frontend: Result { client_id: Str,  message: Str} [NoOp]
-    }
-backend_update_for_host! = |boxed_model, client_id, session_id, msg_bytes|
-    model = Box.unbox boxed_model
+# Frames come straight from the browser, where anyone can send whatever they like over the
+# websocket, so one that does not decode is logged and dropped
+backend_update_for_host! : Box (BackendState _), Internal.Client.HostClient, Str => BackendUpdate _
+backend_update_for_host! = |boxed_state, host_client, msg_bytes|
+    app = Internal.Backend.inner backendApp
+    when app.decode_from_frontend (Str.to_utf8 msg_bytes) is
+        Ok { request_id, msg: to_backend_msg } ->
+            client = Internal.Client.from_host_client host_client
+            msg =
+                if request_id == 0 then
+                    app.update_from_frontend client to_backend_msg
+                else
+                    app.update_from_request { client, id: request_id } to_backend_msg
+
+            update_backend! (Box.unbox boxed_state) msg
+
+        Err InvalidToBackendMsg ->
+            Log.warn! "Dropped a websocket frame that is not a valid ToBackendMsg" [("frame", msg_bytes)]
+            { model: boxed_state, effects: Internal.BackendCmd.no_effects }
+
+# Called by the host for messages posted to `/_galena/msg` by clients without a websocket.
+# Unlike messages from the frontend these are encoded by hand, so a message that does not
+# decode is reported back to the host rather than treated as a platform bug
+backend_rpc_for_host! : Box (BackendState _), Internal.Client.HostClient, List U8 => { decoded : Bool, update : BackendUpdate _ }
+backend_rpc_for_host! = |boxed_state, host_client, msg_bytes|
+    state = Box.unbox boxed_state
     app = Internal.Backend.inner backendApp
 
-    (updated_model, m_to_frontend_msg) =
-        app.update_from_frontend
-            client_id
-            session_id
-            (app.decode_to_backend_msg (Str.to_utf8 msg_bytes))
-        |> app.update! model
+    when app.decode_to_backend_msg msg_bytes is
+        Ok to_backend_msg ->
+            {
+                decoded: Bool.true,
+                update: update_backend! state (app.update_from_frontend (Internal.Client.from_host_client host_client) to_backend_msg),
+            }
+
+        Err InvalidToBackendMsg ->
+            {
+                decoded: Bool.false,
+                update: { model: Box.box state, effects: Internal.BackendCmd.no_effects },
+            }
+
+# Called by the host once an outbound http request made with `Http.request` completes
+backend_http_response_for_host! : Box (BackendState _), U64, Internal.Http.HostResponse => BackendUpdate _
+backend_http_response_for_host! = |boxed_state, request_id, host_response|
+    state = Box.unbox boxed_state
+
+    when Internal.BackendCmd.take_http_handler state.pending request_id is
+        Ok (pending, on_response) ->
+            update_backend! { state & pending } (on_response (Internal.Http.from_host_response host_response))
+
+        Err NotFound ->
+            { model: Box.box state, effects: Internal.BackendCmd.no_effects }
+
+# Called by the host once a transaction started with `Db.query` or `Db.transaction` completes
+backend_db_response_for_host! : Box (BackendState _), U64, Internal.Db.HostResponse => BackendUpdate _
+backend_db_response_for_host! = |boxed_state, request_id, host_response|
+    state = Box.unbox boxed_state
+
+    when Internal.BackendCmd.take_db_handler state.pending request_id is
+        Ok (pending, on_result) ->
+            update_backend! { state & pending } (on_result (Internal.Db.from_host_response host_response))
+
+        Err NotFound ->
+            { model: Box.box state, effects: Internal.BackendCmd.no_effects }
+
+# Asked by the host on startup. `database` is the file name of the app's database or an
+# empty string, `jobs` the names of the registered jobs and `presence` whether the app
+# wants to hear about clients connecting and disconnecting
+backend_config_for_host : I32 -> { database : Str, jobs : List Str, presence : Bool }
+backend_config_for_host = |_|
+    app = Internal.Backend.inner backendApp
 
     {
-        model: Box.box updated_model,
-        to_frontend: Result.map_ok
-            m_to_frontend_msg
-            (|(cid, to_frontend_msg)|
-                msg = Str.from_utf8_lossy (app.encode_to_frontend_msg to_frontend_msg)
-                { client_id: cid, message: msg }
-            ),
+        database: app.database,
+        jobs: List.map app.jobs Internal.Job.job_name,
+        presence: Result.is_ok app.on_presence,
     }
 
+# Called by the host with every connected client whenever one connects or disconnects
+backend_presence_for_host! : Box (BackendState _), List Internal.Client.HostClient => BackendUpdate _
+backend_presence_for_host! = |boxed_state, host_clients|
+    state = Box.unbox boxed_state
+
+    when (Internal.Backend.inner backendApp).on_presence is
+        Ok on_presence ->
+            update_backend! state (on_presence (List.map host_clients Internal.Client.from_host_client))
+
+        Err NoPresence ->
+            { model: Box.box state, effects: Internal.BackendCmd.no_effects }
+
+# Called by the host from its job workers, outside of any update
+backend_run_job_for_host : Str, List U8 -> Internal.Job.HostOutcome
+backend_run_job_for_host = |name, payload|
+    Internal.Job.run (Internal.Backend.inner backendApp).jobs name payload
+
+# Called by the host once a job succeeded or ran out of attempts
+backend_job_done_for_host! : Box (BackendState _), Internal.Job.HostCompletion => BackendUpdate _
+backend_job_done_for_host! = |boxed_state, completion|
+    state = Box.unbox boxed_state
+
+    when Internal.Job.complete (Internal.Backend.inner backendApp).jobs completion is
+        Ok msg ->
+            update_backend! state msg
+
+        Err _ ->
+            { model: Box.box state, effects: Internal.BackendCmd.no_effects }
+
+# Called by the host for http requests that are not for the frontend's static files
+backend_route_for_host! :
+    Box (BackendState _),
+    Internal.Route.HostRequest
+    =>
+    {
+        response : Result Internal.Route.HostResponse [NotFound],
+        update : BackendUpdate _,
+    }
+backend_route_for_host! = |boxed_state, host_request|
+    state = Box.unbox boxed_state
+    app = Internal.Backend.inner backendApp
+
+    when Internal.Route.find app.routes host_request is
+        Ok (request, handler) ->
+            when handler request state.model is
+                Respond response ->
+                    {
+                        response: Ok response,
+                        update: { model: Box.box state, effects: Internal.BackendCmd.no_effects },
+                    }
+
+                Update msg respond ->
+                    (next_state, effects) = step_backend! state msg
+
+                    {
+                        response: Ok (respond next_state.model),
+                        update: { model: Box.box next_state, effects },
+                    }
+
+        Err NotFound ->
+            {
+                response: Err NotFound,
+                update: { model: Box.box state, effects: Internal.BackendCmd.no_effects },
+            }
+
+update_backend! : BackendState _, _ => BackendUpdate _
+update_backend! = |state, msg|
+    (next_state, effects) = step_backend! state msg
+
+    { model: Box.box next_state, effects }
+
+step_backend! : BackendState _, _ => (BackendState _, Internal.BackendCmd.HostEffects)
+step_backend! = |state, msg|
+    app = Internal.Backend.inner backendApp
+    (model, cmd) = app.update! msg state.model
+    (pending, effects) = Internal.BackendCmd.run cmd state.pending app.encode_to_frontend_msg
+
+    ({ model, pending }, effects)
+
 drop = |_| {}